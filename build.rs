@@ -0,0 +1,17 @@
+use std::env;
+
+fn main() {
+    // Declares the custom cfgs for `unexpected_cfgs`. Older versions of Cargo
+    // ignore this.
+    println!("cargo:rustc-check-cfg=cfg(loom)");
+    println!("cargo:rustc-check-cfg=cfg(once_cell_sync)");
+
+    // `sync` is provided by any of these features, and is replaced by the
+    // model checked implementations under `--cfg loom`.
+    let sync = ["STD", "SPIN", "CRITICAL_SECTION"]
+        .iter()
+        .any(|feature| env::var_os(format!("CARGO_FEATURE_{}", feature)).is_some());
+    if sync && env::var_os("CARGO_CFG_LOOM").is_none() {
+        println!("cargo:rustc-cfg=once_cell_sync");
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    convert::TryFrom,
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::sync::{OnceCell, Reloadable, ReloadableGuard};
+
+/// A source of time for `ExpiringLazy`.
+///
+/// The default clock is `MonotonicClock`. Tests can use a clock which they
+/// advance manually, to expire values deterministically.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+}
+
+/// A `Clock` which returns `Instant::now()`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MonotonicClock;
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A value which is initialized on the first access, and re-initialized on
+/// the first access after its time-to-live has passed.
+///
+/// The first access blocks all threads until the value is computed, like
+/// `Lazy` does. Refreshing an expired value doesn't block: the thread which
+/// notices the expiration first runs the initializer, while other threads
+/// keep getting the stale value. If the initializer panics during a refresh,
+/// the panic is propagated to that thread, the stale value is kept, and the
+/// next access tries again.
+///
+/// Note that this type requires the `std` feature.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use once_cell::sync::ExpiringLazy;
+///
+/// static TOKEN: ExpiringLazy<String> = ExpiringLazy::new(Duration::from_secs(3600), || {
+///     println!("fetching a token");
+///     "token".to_string()
+/// });
+///
+/// fn main() {
+///     assert_eq!(*TOKEN.get(), "token");
+///     // Prints "fetching a token" only once, until an hour passes.
+///     assert_eq!(*TOKEN.get(), "token");
+/// }
+/// ```
+pub struct ExpiringLazy<T, F = fn() -> T, C = MonotonicClock> {
+    state: OnceCell<State<T>>,
+    refreshing: AtomicBool,
+    ttl: Duration,
+    init: F,
+    clock: C,
+}
+
+struct State<T> {
+    value: Reloadable<T>,
+    /// When the value was first computed.
+    created: Instant,
+    /// When the current value was computed, in nanoseconds since `created`.
+    refreshed: AtomicU64,
+}
+
+impl<T, F> ExpiringLazy<T, F> {
+    /// Creates a new lazy value, which is re-initialized with `init` when
+    /// it is older than `ttl`.
+    pub const fn new(ttl: Duration, init: F) -> ExpiringLazy<T, F> {
+        ExpiringLazy::with_clock(ttl, init, MonotonicClock)
+    }
+}
+
+impl<T, F, C> ExpiringLazy<T, F, C> {
+    /// Creates a new lazy value, which measures time with `clock`.
+    pub const fn with_clock(ttl: Duration, init: F, clock: C) -> ExpiringLazy<T, F, C> {
+        ExpiringLazy {
+            state: OnceCell::new(),
+            refreshing: AtomicBool::new(false),
+            ttl,
+            init,
+            clock,
+        }
+    }
+
+    /// Returns the time-to-live of the value.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+impl<T, F: Fn() -> T, C: Clock> ExpiringLazy<T, F, C> {
+    /// Gets the value, initializing it on the first access, or refreshing
+    /// it if it has expired.
+    ///
+    /// The returned guard keeps the value alive, even if it is refreshed in
+    /// the meantime.
+    pub fn get(&self) -> ReloadableGuard<T> {
+        let state = self.state.get_or_init(|| {
+            let value = (self.init)();
+            State {
+                value: Reloadable::new(value),
+                created: self.clock.now(),
+                refreshed: AtomicU64::new(0),
+            }
+        });
+        if self.is_expired(state) && self.try_start_refresh() {
+            let _guard = RefreshGuard { refreshing: &self.refreshing };
+            // Someone could have refreshed the value while we were checking.
+            if self.is_expired(state) {
+                state.value.reload(&self.init);
+                let refreshed = self.clock.now().saturating_duration_since(state.created);
+                state.refreshed.store(nanos(refreshed), Ordering::Release);
+            }
+        }
+        state.value.get()
+    }
+
+    fn is_expired(&self, state: &State<T>) -> bool {
+        let refreshed = Duration::from_nanos(state.refreshed.load(Ordering::Acquire));
+        let age = self.clock.now().saturating_duration_since(state.created + refreshed);
+        age >= self.ttl
+    }
+
+    fn try_start_refresh(&self) -> bool {
+        self.refreshing.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok()
+    }
+}
+
+impl<T: fmt::Debug, F, C> fmt::Debug for ExpiringLazy<T, F, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("ExpiringLazy");
+        match self.state.get() {
+            Some(state) => d.field("value", &*state.value.get()),
+            None => d.field("value", &format_args!("<uninit>")),
+        };
+        d.field("ttl", &self.ttl).finish()
+    }
+}
+
+/// Lets other threads refresh the value when dropped, even if the
+/// initializer panics.
+struct RefreshGuard<'a> {
+    refreshing: &'a AtomicBool,
+}
+
+impl Drop for RefreshGuard<'_> {
+    fn drop(&mut self) {
+        self.refreshing.store(false, Ordering::Release);
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
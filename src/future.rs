@@ -0,0 +1,233 @@
+//! `OnceCell` for asynchronous code.
+//!
+//! `sync::OnceCell::get_or_init` blocks the current thread while another
+//! thread runs the initializer, which stalls the executor if it is called from
+//! a task. The `OnceCell` in this module is initialized by a future instead,
+//! and tasks which access the cell while it is being initialized are suspended
+//! until the initializer completes, without blocking their threads.
+//!
+//! The cell doesn't depend on any particular executor. Note that this module
+//! requires the `async` Cargo feature.
+//!
+//! # Example
+//! ```
+//! use once_cell::future::OnceCell;
+//!
+//! static CLIENT: OnceCell<String> = OnceCell::new();
+//!
+//! async fn client() -> &'static String {
+//!     CLIENT.get_or_init_async(|| async { "connected".to_string() }).await
+//! }
+//! ```
+
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+use crate::sync;
+
+/// A thread-safe cell which can be written to only once, by a future.
+///
+/// At most one task at a time runs the initializing future, other tasks
+/// which call `get_or_init_async` wait for it asynchronously.
+///
+/// If the initializing future returns an error, or is dropped before it
+/// completes (for example, because the task which awaited it was cancelled),
+/// the cell stays empty, and one of the waiting tasks runs its own
+/// initializer instead.
+///
+/// # Example
+/// ```
+/// use once_cell::future::OnceCell;
+///
+/// async fn example(cell: &OnceCell<u32>) {
+///     let value = cell.get_or_init_async(|| async { 92 }).await;
+///     assert_eq!(*value, 92);
+///     assert_eq!(cell.get(), Some(&92));
+/// }
+/// ```
+pub struct OnceCell<T> {
+    value: sync::OnceCell<T>,
+    state: Mutex<State>,
+}
+
+struct State {
+    running: bool,
+    next_key: usize,
+    waiters: Vec<(usize, Waker)>,
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> OnceCell<T> {
+        OnceCell::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnceCell").field("value", &self.get()).finish()
+    }
+}
+
+impl<T> OnceCell<T> {
+    /// Creates a new empty cell.
+    pub const fn new() -> OnceCell<T> {
+        OnceCell {
+            value: sync::OnceCell::new(),
+            state: Mutex::new(State { running: false, next_key: 0, waiters: Vec::new() }),
+        }
+    }
+
+    /// Gets the reference to the underlying value.
+    ///
+    /// Returns `None` if the cell is empty, or being initialized. This
+    /// method never blocks.
+    pub fn get(&self) -> Option<&T> {
+        self.value.get()
+    }
+
+    /// Gets the mutable reference to the underlying value.
+    ///
+    /// Returns `None` if the cell is empty.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.value.get_mut()
+    }
+
+    /// Consumes the `OnceCell`, returning the wrapped value. Returns
+    /// `None` if the cell was empty.
+    pub fn into_inner(self) -> Option<T> {
+        self.value.into_inner()
+    }
+
+    /// Gets the contents of the cell, initializing it with the future
+    /// returned by `f` if the cell was empty.
+    ///
+    /// If another task is initializing the cell, waits for it to finish,
+    /// without blocking the thread. `f` is called only if the cell is still
+    /// empty when it's this task's turn to initialize it.
+    ///
+    /// It is an error to access the cell from the initializing future: the
+    /// future would wait for itself, and never complete.
+    pub async fn get_or_init_async<F, Fut>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        enum Void {}
+        match self.get_or_try_init_async(|| async { Ok::<T, Void>(f().await) }).await {
+            Ok(val) => val,
+            Err(void) => match void {},
+        }
+    }
+
+    /// Gets the contents of the cell, initializing it with the future
+    /// returned by `f` if the cell was empty. If the cell was empty and the
+    /// future failed, an error is returned, and the cell stays empty.
+    ///
+    /// If another task is initializing the cell, waits for it to finish,
+    /// without blocking the thread. `f` is called only if the cell is still
+    /// empty when it's this task's turn to initialize it.
+    ///
+    /// It is an error to access the cell from the initializing future: the
+    /// future would wait for itself, and never complete.
+    pub async fn get_or_try_init_async<F, Fut, E>(&self, f: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        let guard = match (Acquire { cell: self, key: None }).await {
+            Some(guard) => guard,
+            None => return Ok(self.get().unwrap()),
+        };
+        // If the future fails, or we are dropped while awaiting it, `guard`
+        // lets one of the waiters try instead.
+        let value = f().await?;
+        let _ = self.value.set(value);
+        drop(guard);
+        Ok(self.get().unwrap())
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // We never panic while holding the lock, but there's no reason to
+        // propagate poisoning anyway.
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Resolves to `None` once the cell is initialized, or to `Some` if the
+/// current task has won the right to initialize it.
+struct Acquire<'a, T> {
+    cell: &'a OnceCell<T>,
+    key: Option<usize>,
+}
+
+impl<'a, T> Future for Acquire<'a, T> {
+    type Output = Option<Guard<'a, T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let cell = self.cell;
+        let mut state = cell.state();
+        // The value is stored before the initializer releases `running`, so
+        // checking it under the lock can't miss a wakeup.
+        if cell.get().is_some() {
+            return Poll::Ready(None);
+        }
+        if !state.running {
+            state.running = true;
+            return Poll::Ready(Some(Guard { cell }));
+        }
+        let key = match self.key {
+            Some(key) => key,
+            None => {
+                let key = state.next_key;
+                state.next_key = state.next_key.wrapping_add(1);
+                self.key = Some(key);
+                key
+            }
+        };
+        match state.waiters.iter_mut().find(|(it, _)| *it == key) {
+            Some((_, waker)) => {
+                if !waker.will_wake(cx.waker()) {
+                    *waker = cx.waker().clone();
+                }
+            }
+            None => state.waiters.push((key, cx.waker().clone())),
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Acquire<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.cell.state().waiters.retain(|(it, _)| *it != key);
+        }
+    }
+}
+
+/// Held by the task which runs the initializer. Wakes up all the waiters
+/// when dropped: if the cell was initialized, they return the value,
+/// otherwise one of them runs its initializer.
+struct Guard<'a, T> {
+    cell: &'a OnceCell<T>,
+}
+
+impl<T> Drop for Guard<'_, T> {
+    fn drop(&mut self) {
+        let waiters = {
+            let mut state = self.cell.state();
+            state.running = false;
+            std::mem::take(&mut state.waiters)
+        };
+        for (_, waker) in waiters {
+            waker.wake();
+        }
+    }
+}
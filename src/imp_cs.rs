@@ -0,0 +1,244 @@
+//! An implementation of `sync::OnceCell` for embedded targets, on top of the
+//! `critical-section` crate. The initializer runs inside a critical section,
+//! which, on a single-core target, typically means with interrupts disabled,
+//! so nothing can observe the cell while it is being initialized. It is used
+//! only when `std` is disabled.
+
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    panic::{RefUnwindSafe, UnwindSafe},
+    ptr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use crate::{poison, policy::PoisonPolicy, CellName};
+
+/// Only loads and stores are used on the state, as targets without
+/// compare-and-swap, like `thumbv6m`, are the main users of this
+/// implementation. Transitions happen inside a critical section instead.
+pub(crate) struct OnceCell<T> {
+    // Some targets only have atomic bytes.
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+const INCOMPLETE: u8 = 0x0;
+const RUNNING: u8 = 0x1;
+const COMPLETE: u8 = 0x2;
+const POISONED: u8 = 0x3;
+const STATUS_MASK: u8 = 0x3;
+// The `PoisonPolicy` of the cell, which never changes.
+const POLICY_SHIFT: u32 = 2;
+const POLICY_MASK: u8 = 0x3 << POLICY_SHIFT;
+
+// Why do we need `T: Send`?
+// Thread A creates a `OnceCell` and shares it with
+// scoped thread B, which fills the cell, which is
+// then destroyed by A. That is, destructor observes
+// a sent value.
+unsafe impl<T: Sync + Send> Sync for OnceCell<T> {}
+unsafe impl<T: Send> Send for OnceCell<T> {}
+
+impl<T: RefUnwindSafe + UnwindSafe> RefUnwindSafe for OnceCell<T> {}
+impl<T: UnwindSafe> UnwindSafe for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    pub(crate) const fn new(policy: PoisonPolicy) -> OnceCell<T> {
+        OnceCell {
+            state: AtomicU8::new(INCOMPLETE | poison::encode(policy) << POLICY_SHIFT),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    pub(crate) fn get(&self) -> Option<&T> {
+        // `Acquire` pairs with the `Release` store in `Guard::drop`, which
+        // happens after the value is written.
+        if self.state.load(Ordering::Acquire) & STATUS_MASK == COMPLETE {
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn set(&self, name: CellName, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(name, || value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    pub(crate) fn get_or_init<F: FnOnce() -> T>(&self, name: CellName, f: F) -> &T {
+        enum Void {}
+        match self.get_or_try_init(name, || Ok::<T, Void>(f())) {
+            Ok(val) => val,
+            Err(void) => match void {},
+        }
+    }
+
+    pub(crate) fn get_or_try_init<F: FnOnce() -> Result<T, E>, E>(
+        &self,
+        name: CellName,
+        f: F,
+    ) -> Result<&T, E> {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        critical_section::with(|_| self.initialize(name, f))?;
+        // `initialize` returns `Ok` only after observing (or storing)
+        // `COMPLETE` inside the critical section.
+        Ok(unsafe { self.get_unchecked() })
+    }
+
+    pub(crate) fn wait(&self, name: CellName) -> &T {
+        loop {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            let state = self.state.load(Ordering::Relaxed);
+            if state & STATUS_MASK == POISONED {
+                poison::poisoned()
+            }
+            // Another thread can be seen running the initializer only with a
+            // multi-core implementation of critical sections, and only from
+            // outside of the critical section, which it holds. So if we can
+            // enter it, and still see the initializer running, that's us.
+            if state & STATUS_MASK == RUNNING
+                && critical_section::with(|_| {
+                    self.state.load(Ordering::Relaxed) & STATUS_MASK == RUNNING
+                })
+            {
+                reentrant(name)
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    pub(crate) fn policy(&self) -> PoisonPolicy {
+        policy(self.state.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.state.load(Ordering::Acquire) & STATUS_MASK == POISONED
+    }
+
+    pub(crate) fn clear_poison(&self) {
+        critical_section::with(|_| {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & STATUS_MASK == POISONED {
+                self.state.store(INCOMPLETE | (state & POLICY_MASK), Ordering::Relaxed);
+            }
+        });
+    }
+
+    pub(crate) fn get_mut(&mut self) -> Option<&mut T> {
+        // Because `get_mut` takes `self` by unique reference, no other thread
+        // can access the cell, so no synchronization is needed.
+        if *self.state.get_mut() & STATUS_MASK == COMPLETE {
+            Some(unsafe { &mut *self.value.get_mut().as_mut_ptr() })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn take(&mut self) -> Option<T> {
+        // Unique reference guarantees that nobody is initializing the cell
+        // right now, so we can simply move the value out, and reset the cell
+        // to the initial state.
+        let state = self.state.get_mut();
+        debug_assert!(*state & STATUS_MASK != RUNNING);
+        if *state & STATUS_MASK != COMPLETE {
+            return None;
+        }
+        *state = INCOMPLETE | (*state & POLICY_MASK);
+        Some(unsafe { ptr::read(self.value.get_mut().as_ptr()) })
+    }
+
+    pub(crate) fn into_inner(mut self) -> Option<T> {
+        // `take` leaves the cell empty, so `drop` has nothing left to do.
+        self.take()
+    }
+
+    /// Must be called inside a critical section, which no other thread can
+    /// enter until it ends, so the state can't change under us.
+    #[cold]
+    fn initialize<F: FnOnce() -> Result<T, E>, E>(&self, name: CellName, f: F) -> Result<(), E> {
+        let state = self.state.load(Ordering::Acquire);
+        match state & STATUS_MASK {
+            COMPLETE => return Ok(()),
+            POISONED => poison::poisoned(),
+            // Critical sections nest, so only the initializer itself can get
+            // here.
+            RUNNING => reentrant(name),
+            _ => (),
+        }
+
+        // If `f` returns `Err`, `guard` resets the state back to
+        // `INCOMPLETE`, so that the next caller can retry. If it panics,
+        // `guard` also poisons the cell, if the policy says so.
+        let policy_bits = state & POLICY_MASK;
+        let policy = policy(state);
+        let panicked = if policy == PoisonPolicy::Retry { INCOMPLETE } else { POISONED };
+        self.state.store(RUNNING | policy_bits, Ordering::Relaxed);
+        let mut guard = Guard { state: &self.state, new_state: panicked | policy_bits };
+        // Payloads are stored only with `std`.
+        let value = match poison::run(policy, f, |_| ()) {
+            Ok(value) => value,
+            Err(err) => {
+                guard.new_state = INCOMPLETE | policy_bits;
+                return Err(err);
+            }
+        };
+        unsafe { (*self.value.get()).as_mut_ptr().write(value) };
+        guard.new_state = COMPLETE | policy_bits;
+        Ok(())
+    }
+
+    /// Safety: the cell must be in the `COMPLETE` state, synchronized with
+    /// this thread.
+    unsafe fn get_unchecked(&self) -> &T {
+        debug_assert_eq!(self.state.load(Ordering::Relaxed) & STATUS_MASK, COMPLETE);
+        &*(*self.value.get()).as_ptr()
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        let state = *self.state.get_mut();
+        if state & STATUS_MASK == COMPLETE {
+            unsafe { ptr::drop_in_place(self.value.get_mut().as_mut_ptr()) }
+        }
+    }
+}
+
+fn policy(state: u8) -> PoisonPolicy {
+    poison::decode((state & POLICY_MASK) >> POLICY_SHIFT)
+}
+
+/// Panics because the initializer of the cell `name` accessed the cell itself.
+#[cold]
+fn reentrant(name: CellName) -> ! {
+    panic!("reentrant init of `{}`: the initializer accessed the cell itself", name)
+}
+
+struct Guard<'a> {
+    state: &'a AtomicU8,
+    new_state: u8,
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        // `Release` publishes the value written under the `RUNNING` state.
+        self.state.store(self.new_state, Ordering::Release);
+    }
+}
+
+#[test]
+fn test_size() {
+    use core::mem::size_of;
+
+    // state byte + `u32`, padded to the alignment of `u32`
+    assert_eq!(size_of::<OnceCell<u32>>(), 2 * size_of::<u32>());
+}
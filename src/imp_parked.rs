@@ -0,0 +1,395 @@
+//! An implementation of `sync::OnceCell` whose lock and init flag are a
+//! single state word. Threads which wait for the cell block on the word
+//! itself, so the cell needs no room for a queue of waiters. How they block
+//! is up to the `park` module: `park_pl.rs` parks them with
+//! `parking_lot_core`, and `park_futex.rs` with the `futex` system call.
+
+use std::{
+    any::Any,
+    mem::MaybeUninit,
+    panic::{RefUnwindSafe, UnwindSafe},
+    ptr,
+    thread::ThreadId,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    owner::{self, Owner},
+    park, poison,
+    policy::PoisonPolicy,
+    primitives::{AtomicU32, Ordering, UnsafeCell},
+    CellName, GiveUp,
+};
+
+/// The state is a `u32` rather than a byte: the `futex` system call only
+/// works on 32-bit words, and the spare bits hold the id of the initializing
+/// thread. With a byte and the id kept elsewhere, the cell would be smaller
+/// only for values aligned to less than 4 bytes, like `OnceCell<u8>`.
+pub(crate) struct OnceCell<T> {
+    state: AtomicU32,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// The low bits of the state: nobody has initialized the cell yet, a thread
+// is running the initializer, the value is stored, or an initializer has
+// panicked, and the policy of the cell is not `Retry`.
+const INCOMPLETE: u32 = 0x0;
+const RUNNING: u32 = 0x1;
+const COMPLETE: u32 = 0x2;
+const POISONED: u32 = 0x3;
+const STATUS_MASK: u32 = 0x3;
+// Set if there might be threads parked on the cell. It is cleared, and the
+// threads are unparked, when the initializer finishes or gives up.
+const PARKED_BIT: u32 = 0x4;
+// The `PoisonPolicy` of the cell, which never changes.
+const POLICY_SHIFT: u32 = 3;
+const POLICY_MASK: u32 = 0x3 << POLICY_SHIFT;
+// In the `RUNNING` state, the rest of the bits are the id of the initializing
+// thread, see `owner::current_id`. In the `POISONED` state with
+// `PoisonPolicy::StorePayload`, they are the index of the panic payload.
+const OWNER_SHIFT: u32 = 5;
+const PAYLOAD_SHIFT: u32 = 5;
+
+// Why do we need `T: Send`?
+// Thread A creates a `OnceCell` and shares it with
+// scoped thread B, which fills the cell, which is
+// then destroyed by A. That is, destructor observes
+// a sent value.
+unsafe impl<T: Sync + Send> Sync for OnceCell<T> {}
+unsafe impl<T: Send> Send for OnceCell<T> {}
+
+impl<T: RefUnwindSafe + UnwindSafe> RefUnwindSafe for OnceCell<T> {}
+impl<T: UnwindSafe> UnwindSafe for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    #[cfg(not(loom))]
+    pub(crate) const fn new(policy: PoisonPolicy) -> OnceCell<T> {
+        OnceCell {
+            state: AtomicU32::new(INCOMPLETE | (poison::encode(policy) as u32) << POLICY_SHIFT),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    #[cfg(loom)]
+    pub(crate) fn new(policy: PoisonPolicy) -> OnceCell<T> {
+        OnceCell {
+            state: AtomicU32::new(INCOMPLETE | (poison::encode(policy) as u32) << POLICY_SHIFT),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    pub(crate) fn get(&self) -> Option<&T> {
+        // `Acquire` pairs with the `Release` swap in `Guard::drop`, which
+        // happens after the value is written. `COMPLETE` is final, so the
+        // parked bit is never set together with it.
+        if self.state.load(Ordering::Acquire) & STATUS_MASK == COMPLETE {
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn set(&self, name: CellName, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(name, || value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    pub(crate) fn get_or_init<F: FnOnce() -> T>(&self, name: CellName, f: F) -> &T {
+        enum Void {}
+        match self.get_or_try_init(name, || Ok::<T, Void>(f())) {
+            Ok(val) => val,
+            Err(void) => match void {},
+        }
+    }
+
+    pub(crate) fn get_or_try_init<F: FnOnce() -> Result<T, E>, E>(
+        &self,
+        name: CellName,
+        f: F,
+    ) -> Result<&T, E> {
+        // Fast path: a single `Acquire` load, like in `get`.
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        match self.initialize(name, f, GiveUp::NEVER) {
+            Some(res) => res?,
+            None => unreachable!(),
+        }
+        // `initialize` returns `Ok` only after observing (or storing)
+        // `COMPLETE`, with "happens before" for the value.
+        Ok(unsafe { self.get_unchecked() })
+    }
+
+    pub(crate) fn get_or_try_init_until<F: FnOnce() -> Result<T, E>, E>(
+        &self,
+        name: CellName,
+        f: F,
+        give_up: GiveUp<'_>,
+    ) -> Option<Result<&T, E>> {
+        if let Some(value) = self.get() {
+            return Some(Ok(value));
+        }
+        let res = self.initialize(name, f, give_up)?;
+        Some(res.map(|()| unsafe { self.get_unchecked() }))
+    }
+
+    pub(crate) fn wait(&self, name: CellName) -> &T {
+        loop {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            self.check_poison();
+            self.park(name, is_empty, None);
+        }
+    }
+
+    pub(crate) fn wait_timeout(&self, name: CellName, timeout: Duration) -> Option<&T> {
+        // If the deadline is not representable, it might as well be never.
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            if let Some(value) = self.get() {
+                return Some(value);
+            }
+            match deadline {
+                Some(deadline) if Instant::now() >= deadline => return None,
+                _ => (),
+            }
+            self.check_poison();
+            self.park(name, is_empty, deadline);
+        }
+    }
+
+    pub(crate) fn initializing_thread(&self) -> Option<ThreadId> {
+        let state = self.state.load(Ordering::Relaxed);
+        if state & STATUS_MASK != RUNNING {
+            return None;
+        }
+        owner::thread(state >> OWNER_SHIFT)
+    }
+
+    pub(crate) fn policy(&self) -> PoisonPolicy {
+        policy(self.state.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.state.load(Ordering::Acquire) & STATUS_MASK == POISONED
+    }
+
+    pub(crate) fn clear_poison(&self) {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state & STATUS_MASK != POISONED {
+                return;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                INCOMPLETE | (state & POLICY_MASK),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(new_state) => state = new_state,
+            }
+        }
+        if policy(state) == PoisonPolicy::StorePayload {
+            poison::free_payload((state >> PAYLOAD_SHIFT) as usize);
+        }
+    }
+
+    pub(crate) fn take_panic_payload(&self) -> Option<Box<dyn Any + Send>> {
+        let state = self.state.load(Ordering::Relaxed);
+        if state & STATUS_MASK != POISONED || policy(state) != PoisonPolicy::StorePayload {
+            return None;
+        }
+        poison::take_payload((state >> PAYLOAD_SHIFT) as usize, || {
+            self.state.load(Ordering::Relaxed) == state
+        })
+    }
+
+    pub(crate) fn get_mut(&mut self) -> Option<&mut T> {
+        // Because `get_mut` takes `self` by unique reference, no other thread
+        // can access the cell, so no synchronization is needed.
+        if self.state.load(Ordering::Relaxed) & STATUS_MASK == COMPLETE {
+            Some(self.value.with_mut(|ptr| unsafe { (*ptr).assume_init_mut() }))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn take(&mut self) -> Option<T> {
+        // Unique reference guarantees that nobody is initializing the cell
+        // right now, so we can simply move the value out, and reset the cell
+        // to the initial state.
+        let state = self.state.load(Ordering::Relaxed);
+        debug_assert!(state & STATUS_MASK != RUNNING);
+        if state & STATUS_MASK != COMPLETE {
+            return None;
+        }
+        self.state.store(INCOMPLETE | (state & POLICY_MASK), Ordering::Relaxed);
+        Some(self.value.with(|ptr| unsafe { ptr::read(ptr).assume_init() }))
+    }
+
+    pub(crate) fn into_inner(mut self) -> Option<T> {
+        // `take` leaves the cell empty, so `drop` has nothing left to do.
+        self.take()
+    }
+
+    /// Returns `None` if it's time to `give_up` while another thread is
+    /// running the initializer. If the cell is empty, runs `f` regardless.
+    #[cold]
+    fn initialize<F: FnOnce() -> Result<T, E>, E>(
+        &self,
+        name: CellName,
+        f: F,
+        give_up: GiveUp<'_>,
+    ) -> Option<Result<(), E>> {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            match state & STATUS_MASK {
+                COMPLETE => return Some(Ok(())),
+                POISONED => poison::poisoned(),
+                INCOMPLETE => {
+                    // Keep the parked bit, the waiters are woken up once we
+                    // are done.
+                    let owner = owner::current_id() << OWNER_SHIFT;
+                    match self.state.compare_exchange_weak(
+                        state,
+                        RUNNING | owner | (state & (PARKED_BIT | POLICY_MASK)),
+                        Ordering::Acquire,
+                        Ordering::Acquire,
+                    ) {
+                        Ok(_) => break,
+                        Err(new_state) => state = new_state,
+                    }
+                }
+                // Someone else is running the initializer: wait until it
+                // either succeeds or gives up, and try again.
+                _ => {
+                    if give_up.due() {
+                        return None;
+                    }
+                    self.park(name, |state| state & STATUS_MASK == RUNNING, give_up.wake_at());
+                    state = self.state.load(Ordering::Acquire);
+                }
+            }
+        }
+
+        // We are the only thread in the `RUNNING` state, so we can touch
+        // the slot. We are careful with the user-supplied function:
+        // - if it returns `Err`, `guard` resets the state back to
+        //   `INCOMPLETE`, so that some other thread can retry. If it panics,
+        //   `guard` also poisons the cell, if the policy says so.
+        // - if it calls `set` or `get_or_try_init` re-entrantly, we must not
+        //   get to the slot, which is important for safety. We register
+        //   ourselves as the owner of the cell, so that `park` panics instead
+        //   of deadlocking.
+        let policy_bits = state & POLICY_MASK;
+        let policy = policy(state);
+        let panicked = if policy == PoisonPolicy::Retry { INCOMPLETE } else { POISONED };
+        let mut guard = Guard { state: &self.state, new_state: panicked | policy_bits };
+        let res = {
+            let _owner = Owner::register(self as *const Self as usize);
+            poison::run(policy, f, |index| guard.new_state |= (index as u32) << PAYLOAD_SHIFT)
+        };
+        let value = match res {
+            Ok(value) => value,
+            Err(err) => {
+                guard.new_state = INCOMPLETE | policy_bits;
+                return Some(Err(err));
+            }
+        };
+        self.value.with_mut(|ptr| unsafe { (*ptr).as_mut_ptr().write(value) });
+        guard.new_state = COMPLETE | policy_bits;
+        Some(Ok(()))
+    }
+
+    /// Safety: the cell must be in the `COMPLETE` state, synchronized with
+    /// this thread.
+    unsafe fn get_unchecked(&self) -> &T {
+        debug_assert_eq!(self.state.load(Ordering::Relaxed) & STATUS_MASK, COMPLETE);
+        self.value.with(|ptr| (*ptr).assume_init_ref())
+    }
+
+    fn check_poison(&self) {
+        if self.is_poisoned() {
+            poison::poisoned()
+        }
+    }
+
+    /// Blocks the current thread until the cell leaves its current state, the
+    /// `deadline` passes, or a spurious wakeup. Returns at once if
+    /// `should_wait` doesn't hold for the current state: for example, the
+    /// initializer we were going to wait for might have failed already, and
+    /// nobody would wake us up.
+    #[cold]
+    fn park(&self, name: CellName, should_wait: fn(u32) -> bool, deadline: Option<Instant>) {
+        // Waiting for the value we are supposed to produce would deadlock.
+        owner::check_reentrancy(self as *const Self as usize, name);
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if !should_wait(state & STATUS_MASK) {
+                return;
+            }
+            if state & PARKED_BIT != 0 {
+                break;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state | PARKED_BIT,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    state |= PARKED_BIT;
+                    break;
+                }
+                Err(new_state) => state = new_state,
+            }
+        }
+        // `park::wait` checks that the state is still `state` atomically with
+        // going to sleep, and `Guard::drop` wakes everyone up after it changes
+        // the state, so we can't miss a wakeup.
+        park::wait(&self.state, state, deadline);
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        let state = self.state.load(Ordering::Relaxed);
+        if state & STATUS_MASK == COMPLETE {
+            self.value.with_mut(|ptr| unsafe { ptr::drop_in_place((*ptr).as_mut_ptr()) });
+        }
+        if state & STATUS_MASK == POISONED && policy(state) == PoisonPolicy::StorePayload {
+            poison::free_payload((state >> PAYLOAD_SHIFT) as usize);
+        }
+    }
+}
+
+fn policy(state: u32) -> PoisonPolicy {
+    poison::decode(((state & POLICY_MASK) >> POLICY_SHIFT) as u8)
+}
+
+/// Whether `wait` should wait in the `status`: nobody has stored the value
+/// yet, and it isn't poisoned.
+fn is_empty(status: u32) -> bool {
+    status == INCOMPLETE || status == RUNNING
+}
+
+struct Guard<'a> {
+    state: &'a AtomicU32,
+    new_state: u32,
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        // `Release` publishes the value written under the `RUNNING` state.
+        let state = self.state.swap(self.new_state, Ordering::Release);
+        if state & PARKED_BIT != 0 {
+            park::wake_all(self.state);
+        }
+    }
+}
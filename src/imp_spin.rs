@@ -0,0 +1,244 @@
+//! An implementation of `sync::OnceCell` without `std`, which spins on an
+//! atomic while another thread runs the initializer. It is used only when
+//! `std` is disabled: with `std`, threads can block instead.
+
+use core::{
+    hint::unreachable_unchecked,
+    panic::{RefUnwindSafe, UnwindSafe},
+};
+
+use crate::{
+    poison,
+    policy::PoisonPolicy,
+    primitives::{spin_loop, AtomicU8, Ordering, UnsafeCell},
+    CellName,
+};
+
+pub(crate) struct OnceCell<T> {
+    state: AtomicU8,
+    value: UnsafeCell<Option<T>>,
+}
+
+const INCOMPLETE: u8 = 0x0;
+const RUNNING: u8 = 0x1;
+const COMPLETE: u8 = 0x2;
+const POISONED: u8 = 0x3;
+const STATUS_MASK: u8 = 0x3;
+// The `PoisonPolicy` of the cell, which never changes.
+const POLICY_SHIFT: u32 = 2;
+const POLICY_MASK: u8 = 0x3 << POLICY_SHIFT;
+
+// Why do we need `T: Send`?
+// Thread A creates a `OnceCell` and shares it with
+// scoped thread B, which fills the cell, which is
+// then destroyed by A. That is, destructor observes
+// a sent value.
+unsafe impl<T: Sync + Send> Sync for OnceCell<T> {}
+unsafe impl<T: Send> Send for OnceCell<T> {}
+
+impl<T: RefUnwindSafe + UnwindSafe> RefUnwindSafe for OnceCell<T> {}
+impl<T: UnwindSafe> UnwindSafe for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    #[cfg(not(loom))]
+    pub(crate) const fn new(policy: PoisonPolicy) -> OnceCell<T> {
+        OnceCell {
+            state: AtomicU8::new(INCOMPLETE | poison::encode(policy) << POLICY_SHIFT),
+            value: UnsafeCell::new(None),
+        }
+    }
+
+    #[cfg(loom)]
+    pub(crate) fn new(policy: PoisonPolicy) -> OnceCell<T> {
+        OnceCell {
+            state: AtomicU8::new(INCOMPLETE | poison::encode(policy) << POLICY_SHIFT),
+            value: UnsafeCell::new(None),
+        }
+    }
+
+    pub(crate) fn get(&self) -> Option<&T> {
+        // `Acquire` pairs with the `Release` store in `Guard::drop`, which
+        // happens after the value is written.
+        if self.state.load(Ordering::Acquire) & STATUS_MASK == COMPLETE {
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn set(&self, name: CellName, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(name, || value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    pub(crate) fn get_or_init<F: FnOnce() -> T>(&self, name: CellName, f: F) -> &T {
+        enum Void {}
+        match self.get_or_try_init(name, || Ok::<T, Void>(f())) {
+            Ok(val) => val,
+            Err(void) => match void {},
+        }
+    }
+
+    /// Without the identity of the current thread, re-entrant initialization
+    /// can't be told apart from a slow initializer on another thread, so
+    /// `_name` is unused.
+    pub(crate) fn get_or_try_init<F: FnOnce() -> Result<T, E>, E>(
+        &self,
+        _name: CellName,
+        f: F,
+    ) -> Result<&T, E> {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        self.initialize(f)?;
+        // `initialize` returns `Ok` only after observing (or storing)
+        // `COMPLETE`, with "happens before" for the value.
+        Ok(unsafe { self.get_unchecked() })
+    }
+
+    /// Re-entrant waiting spins forever, as we have no way to learn the
+    /// identity of the current thread.
+    pub(crate) fn wait(&self, _name: CellName) -> &T {
+        loop {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            if self.is_poisoned() {
+                poison::poisoned()
+            }
+            spin_loop();
+        }
+    }
+
+    pub(crate) fn policy(&self) -> PoisonPolicy {
+        policy(self.state.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.state.load(Ordering::Acquire) & STATUS_MASK == POISONED
+    }
+
+    pub(crate) fn clear_poison(&self) {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state & STATUS_MASK != POISONED {
+                return;
+            }
+            let exchange = self.state.compare_exchange_weak(
+                state,
+                INCOMPLETE | (state & POLICY_MASK),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+            match exchange {
+                Ok(_) => break,
+                Err(new_state) => state = new_state,
+            }
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> Option<&mut T> {
+        // Because `get_mut` takes `self` by unique reference, no other thread
+        // can access the cell, so no synchronization is needed.
+        self.value.with_mut(|ptr| unsafe { &mut *ptr }).as_mut()
+    }
+
+    pub(crate) fn take(&mut self) -> Option<T> {
+        // Unique reference guarantees that nobody is initializing the cell
+        // right now, so we can simply reset it to the initial state.
+        let value = self.value.with_mut(|ptr| unsafe { &mut *ptr }).take();
+        let state = self.state.load(Ordering::Relaxed);
+        debug_assert!(state & STATUS_MASK != RUNNING);
+        // A poisoned cell stays poisoned.
+        if state & STATUS_MASK == COMPLETE {
+            self.state.store(INCOMPLETE | (state & POLICY_MASK), Ordering::Relaxed);
+        }
+        value
+    }
+
+    pub(crate) fn into_inner(mut self) -> Option<T> {
+        self.take()
+    }
+
+    #[cold]
+    fn initialize<F: FnOnce() -> Result<T, E>, E>(&self, f: F) -> Result<(), E> {
+        let policy_bits = self.state.load(Ordering::Relaxed) & POLICY_MASK;
+        loop {
+            let exchange = self.state.compare_exchange_weak(
+                INCOMPLETE | policy_bits,
+                RUNNING | policy_bits,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            );
+            match exchange.map_err(|state| state & STATUS_MASK) {
+                Ok(_) => break,
+                Err(COMPLETE) => return Ok(()),
+                Err(POISONED) => poison::poisoned(),
+                // Someone else is running the initializer: spin until it
+                // either succeeds or gives up, and try again. Re-entrant
+                // initialization spins forever, as we have no way to learn
+                // the identity of the current thread.
+                Err(RUNNING) => {
+                    while self.state.load(Ordering::Relaxed) & STATUS_MASK == RUNNING {
+                        spin_loop();
+                    }
+                }
+                Err(_) => (),
+            }
+        }
+
+        // We are the only thread in the `RUNNING` state, so we can touch
+        // the slot. If `f` returns `Err`, `guard` resets the state back to
+        // `INCOMPLETE`, so that some other thread can retry. If it panics,
+        // `guard` also poisons the cell, if the policy says so.
+        let policy = policy(policy_bits);
+        let panicked = if policy == PoisonPolicy::Retry { INCOMPLETE } else { POISONED };
+        let mut guard = Guard { state: &self.state, new_state: panicked | policy_bits };
+        // Payloads are stored only with `std`.
+        let value = match poison::run(policy, f, |_| ()) {
+            Ok(value) => value,
+            Err(err) => {
+                guard.new_state = INCOMPLETE | policy_bits;
+                return Err(err);
+            }
+        };
+        let slot: &mut Option<T> = self.value.with_mut(|ptr| unsafe { &mut *ptr });
+        debug_assert!(slot.is_none());
+        *slot = Some(value);
+        guard.new_state = COMPLETE | policy_bits;
+        Ok(())
+    }
+
+    /// Safety: the cell must be in the `COMPLETE` state, synchronized with
+    /// this thread.
+    unsafe fn get_unchecked(&self) -> &T {
+        let slot: &Option<T> = self.value.with(|ptr| &*ptr);
+        match slot {
+            Some(value) => value,
+            None => {
+                debug_assert!(false);
+                unreachable_unchecked()
+            }
+        }
+    }
+}
+
+fn policy(state: u8) -> PoisonPolicy {
+    poison::decode((state & POLICY_MASK) >> POLICY_SHIFT)
+}
+
+struct Guard<'a> {
+    state: &'a AtomicU8,
+    new_state: u8,
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        // `Release` publishes the value written under the `RUNNING` state.
+        self.state.store(self.new_state, Ordering::Release);
+    }
+}
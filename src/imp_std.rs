@@ -1,17 +1,42 @@
+// There's a lot of scary concurrent code in this module, but it is copied from
+// `std::sync::Once` with four changes:
+//   * poisoning is up to the `PoisonPolicy` of the cell
+//   * init function can fail
+//   * re-entrant initialization panics instead of deadlocking
+//   * waiters are reference counted, so that they can give up waiting on
+//     timeout, and they can queue up on an incomplete cell, to support `wait`
+
 use std::{
-    cell::UnsafeCell,
-    panic::{UnwindSafe, RefUnwindSafe},
-    sync::{
-        Once,
-        atomic::{AtomicBool, Ordering},
+    any::Any,
+    cell::{Cell, RefCell},
+    hint::unreachable_unchecked,
+    marker::PhantomData,
+    panic::{RefUnwindSafe, UnwindSafe},
+    ptr,
+    thread::ThreadId,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    owner::{self, Owner},
+    poison,
+    policy::PoisonPolicy,
+    primitives::{
+        self, thread, Arc, AtomicBool, AtomicU32, AtomicUsize, Ordering, Thread, UnsafeCell,
     },
+    CellName, GiveUp,
 };
 
-#[derive(Debug)]
 pub(crate) struct OnceCell<T> {
-    once: Once,
+    // This `state` word is actually an encoded version of just a pointer to a
+    // `Waiter`, so we add the `PhantomData` appropriately.
+    state_and_queue: AtomicUsize,
+    _marker: PhantomData<*mut Waiter>,
+    // Difference, std doesn't track the initializing thread. The queue leaves
+    // no room for it in `state_and_queue`, so this is the id of the thread
+    // while the cell is `RUNNING`, see `owner::current_id`, and `0` otherwise.
+    owner: AtomicU32,
     value: UnsafeCell<Option<T>>,
-    is_initialized: AtomicBool,
 }
 
 // Why do we need `T: Send`?
@@ -25,72 +50,585 @@ unsafe impl<T: Send> Send for OnceCell<T> {}
 impl<T: RefUnwindSafe + UnwindSafe> RefUnwindSafe for OnceCell<T> {}
 impl<T: UnwindSafe> UnwindSafe for OnceCell<T> {}
 
+// Four states that a OnceCell can be in, encoded into the lower bits of `state` in
+// the OnceCell structure.
+const INCOMPLETE: usize = 0x0;
+const RUNNING: usize = 0x1;
+const COMPLETE: usize = 0x2;
+const POISONED: usize = 0x3;
+
+// Mask to learn about the state.
+const STATE_MASK: usize = 0x3;
+
+// The next two bits are the `PoisonPolicy` of the cell, which never changes.
+// All other bits are the queue of waiters if this is in the RUNNING or
+// INCOMPLETE state, or the index of the panic payload if this is in the
+// POISONED state with `PoisonPolicy::StorePayload`.
+const POLICY_SHIFT: usize = 2;
+const POLICY_MASK: usize = 0x3 << POLICY_SHIFT;
+const QUEUE_SHIFT: usize = 4;
+const QUEUE_MASK: usize = !0 << QUEUE_SHIFT;
+
+// Representation of a node in the linked list of waiters.
+// Difference, std stores a waiter on the stack of the waiting thread. We put
+// it into an `Arc` shared by the thread and the queue instead, so that a thread
+// which has timed out can return while its node is still in the queue.
+// The four lowest bits of the node's address are used for the state and the
+// policy, hence the alignment requirement.
+#[repr(align(16))]
+struct Waiter {
+    thread: Cell<Option<Thread>>,
+    signaled: AtomicBool,
+    next: Cell<*const Waiter>,
+}
+
+// The cells of a node are written by the waiting thread before the node is
+// published with `Release`, and afterwards only by the single thread which
+// dequeues it, so a node can be shared between threads.
+unsafe impl Sync for Waiter {}
+unsafe impl Send for Waiter {}
+
+// Head of a linked list of waiters.
+// Every node is an `Arc`, one reference to which is owned by the list.
+// Will wake up the waiters when it gets dropped, i.e. also on panic.
+struct WaiterQueue<'a> {
+    state_and_queue: &'a AtomicUsize,
+    owner: &'a AtomicU32,
+    set_state_on_drop_to: usize,
+}
+
 impl<T> OnceCell<T> {
-    pub(crate) const fn new() -> OnceCell<T> {
+    #[cfg(not(loom))]
+    pub(crate) const fn new(policy: PoisonPolicy) -> OnceCell<T> {
+        OnceCell {
+            state_and_queue: AtomicUsize::new(
+                INCOMPLETE | (poison::encode(policy) as usize) << POLICY_SHIFT,
+            ),
+            _marker: PhantomData,
+            owner: AtomicU32::new(0),
+            value: UnsafeCell::new(None),
+        }
+    }
+
+    #[cfg(loom)]
+    pub(crate) fn new(policy: PoisonPolicy) -> OnceCell<T> {
         OnceCell {
-            once: Once::new(),
+            state_and_queue: AtomicUsize::new(
+                INCOMPLETE | (poison::encode(policy) as usize) << POLICY_SHIFT,
+            ),
+            _marker: PhantomData,
+            owner: AtomicU32::new(0),
             value: UnsafeCell::new(None),
-            is_initialized: AtomicBool::new(false),
         }
     }
 
     pub(crate) fn get(&self) -> Option<&T> {
-        // This might be a hot path, so use `Acquire` here.
-        // It synchronizes with the corresponding `SeqCst`
-        // in `set_inner`, which ensures that, when we read the
-        // `T` out of the slot below, it was indeed fully written
-        // by `set_inner`.
-        if self.is_initialized.load(Ordering::Acquire) {
-            let slot: &Option<T> = unsafe { &*self.value.get() };
-            slot.as_ref()
+        if self.is_initialized() {
+            // Safe b/c checked is_initialized
+            Some(unsafe { self.get_unchecked() })
         } else {
             None
         }
     }
 
-    pub(crate) fn set(&self, value: T) -> Result<(), T> {
+    pub(crate) fn set(&self, name: CellName, value: T) -> Result<(), T> {
         let mut value = Some(value);
-        self.once.call_once(|| {
-            let value = value.take().unwrap();
-            unsafe { self.set_inner(value) }
-        });
+        self.get_or_init(name, || value.take().unwrap());
         match value {
             None => Ok(()),
             Some(value) => Err(value),
         }
     }
 
-    pub(crate) fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
-        self.once.call_once(|| {
-            let value = f();
-            unsafe {
-                self.set_inner(value);
+    pub(crate) fn get_or_init<F: FnOnce() -> T>(&self, name: CellName, f: F) -> &T {
+        enum Void {}
+        match self.get_or_try_init(name, || Ok::<T, Void>(f())) {
+            Ok(val) => val,
+            Err(void) => match void {},
+        }
+    }
+
+    pub(crate) fn get_or_try_init<F: FnOnce() -> Result<T, E>, E>(
+        &self,
+        name: CellName,
+        f: F,
+    ) -> Result<&T, E> {
+        // Fast path check
+        if !self.is_initialized() {
+            match self.initialize(name, f, GiveUp::NEVER) {
+                Some(res) => res?,
+                None => unreachable!(),
+            }
+        }
+        // Value is initialized here, either because we've observed `COMPLETE`
+        // with `Acquire` on the fast path, or because `initialize` returned
+        // `Ok`, which also implies `COMPLETE` with "happens before".
+        debug_assert!(self.is_initialized());
+        Ok(unsafe { self.get_unchecked() })
+    }
+
+    pub(crate) fn get_or_try_init_until<F: FnOnce() -> Result<T, E>, E>(
+        &self,
+        name: CellName,
+        f: F,
+        give_up: GiveUp<'_>,
+    ) -> Option<Result<&T, E>> {
+        if !self.is_initialized() {
+            if let Err(err) = self.initialize(name, f, give_up)? {
+                return Some(Err(err));
+            }
+        }
+        debug_assert!(self.is_initialized());
+        Some(Ok(unsafe { self.get_unchecked() }))
+    }
+
+    pub(crate) fn wait(&self, name: CellName) -> &T {
+        if !self.is_initialized() {
+            wait_complete(&self.state_and_queue, name, GiveUp::NEVER);
+        }
+        debug_assert!(self.is_initialized());
+        unsafe { self.get_unchecked() }
+    }
+
+    pub(crate) fn wait_timeout(&self, name: CellName, timeout: Duration) -> Option<&T> {
+        if !self.is_initialized() {
+            // If the deadline is not representable, it might as well be never.
+            let give_up = GiveUp { deadline: Instant::now().checked_add(timeout), cancel: None };
+            if !wait_complete(&self.state_and_queue, name, give_up) {
+                return None;
             }
-        });
-        // Value is definitely initialized here, so we don't need
-        // synchronization or matching of None. While we can use `Self::get`
-        // here, that is twice as slow!
-        unsafe {
-            let value: &Option<T> = &*self.value.get();
-            match value.as_ref() {
-                Some(it) => it,
-                None => std::hint::unreachable_unchecked(),
+        }
+        debug_assert!(self.is_initialized());
+        Some(unsafe { self.get_unchecked() })
+    }
+
+    pub(crate) fn initializing_thread(&self) -> Option<ThreadId> {
+        if self.state_and_queue.load(Ordering::Relaxed) & STATE_MASK != RUNNING {
+            return None;
+        }
+        owner::thread(self.owner.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn policy(&self) -> PoisonPolicy {
+        policy(self.state_and_queue.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.state_and_queue.load(Ordering::Acquire) & STATE_MASK == POISONED
+    }
+
+    pub(crate) fn clear_poison(&self) {
+        let mut state_and_queue = self.state_and_queue.load(Ordering::Relaxed);
+        loop {
+            if state_and_queue & STATE_MASK != POISONED {
+                return;
             }
+            // Nobody waits on a poisoned cell, so there's no queue to keep.
+            let exchange = self.state_and_queue.compare_exchange_weak(
+                state_and_queue,
+                INCOMPLETE | (state_and_queue & POLICY_MASK),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+            match exchange {
+                Ok(_) => break,
+                Err(old) => state_and_queue = old,
+            }
+        }
+        if policy(state_and_queue) == PoisonPolicy::StorePayload {
+            poison::free_payload(state_and_queue >> QUEUE_SHIFT);
+        }
+    }
+
+    pub(crate) fn take_panic_payload(&self) -> Option<Box<dyn Any + Send>> {
+        let state_and_queue = self.state_and_queue.load(Ordering::Relaxed);
+        if state_and_queue & STATE_MASK != POISONED
+            || policy(state_and_queue) != PoisonPolicy::StorePayload
+        {
+            return None;
         }
+        poison::take_payload(state_and_queue >> QUEUE_SHIFT, || {
+            self.state_and_queue.load(Ordering::Relaxed) == state_and_queue
+        })
     }
 
-    pub(crate) fn into_inner(self) -> Option<T> {
+    pub(crate) fn get_mut(&mut self) -> Option<&mut T> {
+        // Because `get_mut` takes `self` by unique reference, no other thread
+        // can access the cell, so no synchronization is needed.
+        self.value.with_mut(|ptr| unsafe { &mut *ptr }).as_mut()
+    }
+
+    pub(crate) fn take(&mut self) -> Option<T> {
+        // Unique reference guarantees that nobody is initializing the cell
+        // right now, so we can simply reset it to the initial state.
+        let value = self.value.with_mut(|ptr| unsafe { &mut *ptr }).take();
+        let state_and_queue = self.state_and_queue.load(Ordering::Relaxed);
+        debug_assert!(state_and_queue & STATE_MASK != RUNNING);
+        // A poisoned cell stays poisoned.
+        if state_and_queue & STATE_MASK == POISONED {
+            return value;
+        }
+        // Only nodes of the threads which have timed out can be left in the
+        // queue, as nobody else can be waiting on the cell.
+        unsafe { wake_all(state_and_queue & QUEUE_MASK) };
+        self.state_and_queue.store(INCOMPLETE | (state_and_queue & POLICY_MASK), Ordering::Relaxed);
+        value
+    }
+
+    pub(crate) fn into_inner(mut self) -> Option<T> {
         // Because `into_inner` takes `self` by value, the compiler statically verifies
         // that it is not currently borrowed. So it is safe to move out `Option<T>`.
-        self.value.into_inner()
+        // The rest of the cell is cleaned up by `drop`.
+        self.take()
+    }
+
+    /// Safety: synchronizes with store to value via Release/Acquire.
+    #[inline]
+    fn is_initialized(&self) -> bool {
+        // An `Acquire` load is enough because that makes all the initialization
+        // operations visible to us, and, this being a fast path, weaker
+        // ordering helps with performance. This `Acquire` synchronizes with
+        // the `AcqRel` swap in `WaiterQueue::drop` on the slow path.
+        self.state_and_queue.load(Ordering::Acquire) & STATE_MASK == COMPLETE
     }
 
-    // Unsafe, because must be guarded by `self.once`.
-    unsafe fn set_inner(&self, value: T) {
-        let slot: &mut Option<T> = &mut *self.value.get();
-        *slot = Some(value);
-        // This is a cold path, so, while `Release` should be enough,
-        // there's no reason not to use `SeqCst`.
-        self.is_initialized.store(true, Ordering::SeqCst);
+    /// Safety: synchronizes with store to value via AcqRel swap of state,
+    /// writes value only once because we never get to INCOMPLETE state after a
+    /// successful write.
+    ///
+    /// Returns `None` if it's time to `give_up` while another thread is
+    /// running the initializer.
+    #[cold]
+    fn initialize<F, E>(&self, name: CellName, f: F, give_up: GiveUp<'_>) -> Option<Result<(), E>>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        let mut f = Some(f);
+        let mut res: Result<(), E> = Ok(());
+        let slot = &self.value;
+        initialize_inner(&self.state_and_queue, &self.owner, name, give_up, &mut || {
+            let f = f.take().unwrap();
+            match f() {
+                Ok(value) => {
+                    slot.with_mut(|ptr| unsafe { *ptr = Some(value) });
+                    true
+                }
+                Err(err) => {
+                    res = Err(err);
+                    false
+                }
+            }
+        })?;
+        Some(res)
     }
+
+    /// Get the reference to the underlying value, without checking if the cell
+    /// is initialized.
+    ///
+    /// Safety:
+    ///
+    /// Caller must ensure that the cell is in initialized state, and that
+    /// the contents are acquired by (synchronized to) this thread.
+    unsafe fn get_unchecked(&self) -> &T {
+        let slot: &Option<T> = self.value.with(|ptr| &*ptr);
+        match slot {
+            Some(value) => value,
+            // This unsafe does improve performance, see `examples/bench`.
+            None => {
+                debug_assert!(false);
+                unreachable_unchecked()
+            }
+        }
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        // Free the nodes of the threads which have timed out, if any, or the
+        // panic payload. We have a unique reference, so `Relaxed` is enough.
+        let state_and_queue = self.state_and_queue.load(Ordering::Relaxed);
+        if state_and_queue & STATE_MASK != POISONED {
+            unsafe { wake_all(state_and_queue & QUEUE_MASK) };
+        } else if policy(state_and_queue) == PoisonPolicy::StorePayload {
+            poison::free_payload(state_and_queue >> QUEUE_SHIFT);
+        }
+    }
+}
+
+fn policy(state_and_queue: usize) -> PoisonPolicy {
+    poison::decode(((state_and_queue & POLICY_MASK) >> POLICY_SHIFT) as u8)
+}
+
+/// Whether a thread can wait in the `state`: the value is not stored yet, and
+/// the cell is not poisoned.
+fn is_empty(state_and_queue: usize) -> bool {
+    let state = state_and_queue & STATE_MASK;
+    state == INCOMPLETE || state == RUNNING
+}
+
+// Corresponds to `std::sync::Once::call_inner`. Returns `None` if it's time to
+// `give_up` while another thread is running the initializer.
+// Note: this is intentionally monomorphic
+fn initialize_inner(
+    my_state_and_queue: &AtomicUsize,
+    my_owner: &AtomicU32,
+    name: CellName,
+    give_up: GiveUp<'_>,
+    init: &mut dyn FnMut() -> bool,
+) -> Option<bool> {
+    // Identifies the cell in the registry of initializing threads.
+    let cell = my_state_and_queue as *const AtomicUsize as usize;
+    let mut state_and_queue = my_state_and_queue.load(Ordering::Acquire);
+
+    loop {
+        match state_and_queue & STATE_MASK {
+            COMPLETE => return Some(true),
+            POISONED => poison::poisoned(),
+            INCOMPLETE => {
+                // Difference, std never has waiters in the `INCOMPLETE` state,
+                // we keep the queue of the threads blocked in `wait`.
+                let exchange = my_state_and_queue.compare_exchange(
+                    state_and_queue,
+                    state_and_queue | RUNNING,
+                    Ordering::Acquire,
+                    Ordering::Acquire,
+                );
+                if let Err(old) = exchange {
+                    state_and_queue = old;
+                    continue;
+                }
+                // Difference, std always poisons
+                let policy_bits = state_and_queue & POLICY_MASK;
+                let policy = policy(state_and_queue);
+                let panicked = if policy == PoisonPolicy::Retry { INCOMPLETE } else { POISONED };
+                let mut waiter_queue = WaiterQueue {
+                    state_and_queue: my_state_and_queue,
+                    owner: my_owner,
+                    set_state_on_drop_to: panicked | policy_bits,
+                };
+                my_owner.store(owner::current_id(), Ordering::Relaxed);
+                // Difference, std doesn't detect re-entrancy and deadlocks
+                let owner = Owner::register(cell);
+                let success = poison::run(policy, init, |index| {
+                    waiter_queue.set_state_on_drop_to |= index << QUEUE_SHIFT
+                });
+                drop(owner);
+
+                // Difference, std always uses `COMPLETE`
+                let state = if success { COMPLETE } else { INCOMPLETE };
+                waiter_queue.set_state_on_drop_to = state | policy_bits;
+                return Some(success);
+            }
+            _ => {
+                assert!(state_and_queue & STATE_MASK == RUNNING);
+                owner::check_reentrancy(cell, name);
+                // Difference, std can't give up.
+                let woken = wait(
+                    my_state_and_queue,
+                    state_and_queue,
+                    |state| state & STATE_MASK == RUNNING,
+                    give_up,
+                );
+                if !woken {
+                    return None;
+                }
+                state_and_queue = my_state_and_queue.load(Ordering::Acquire);
+            }
+        }
+    }
+}
+
+// Blocks until the cell is `COMPLETE`, or until it's time to `give_up`. Returns
+// `true` if the cell is `COMPLETE`.
+// Note: this is intentionally monomorphic
+#[cold]
+fn wait_complete(my_state_and_queue: &AtomicUsize, name: CellName, give_up: GiveUp<'_>) -> bool {
+    let cell = my_state_and_queue as *const AtomicUsize as usize;
+    loop {
+        let state_and_queue = my_state_and_queue.load(Ordering::Acquire);
+        match state_and_queue & STATE_MASK {
+            COMPLETE => return true,
+            POISONED => poison::poisoned(),
+            RUNNING => owner::check_reentrancy(cell, name),
+            _ => (),
+        }
+        if !wait(my_state_and_queue, state_and_queue, is_empty, give_up) {
+            return my_state_and_queue.load(Ordering::Acquire) & STATE_MASK == COMPLETE;
+        }
+    }
+}
+
+// Blocks until the queue is woken up, if `should_wait` holds for the current
+// state. Returns `false` if it's time to `give_up` before that.
+fn wait(
+    state_and_queue: &AtomicUsize,
+    mut current_state: usize,
+    should_wait: fn(usize) -> bool,
+    give_up: GiveUp<'_>,
+) -> bool {
+    if !should_wait(current_state) {
+        return true;
+    }
+    let cell = state_and_queue as *const AtomicUsize as usize;
+    // Difference, std can't give up. A node we've left in the queue when we
+    // gave up waiting on this cell is woken up with the others, so we wait on
+    // it again rather than enqueue another one.
+    if let Some(node) = take_abandoned(cell) {
+        return park(cell, node, give_up);
+    }
+
+    let node = Arc::new(Waiter {
+        thread: Cell::new(Some(thread::current())),
+        signaled: AtomicBool::new(false),
+        next: Cell::new(ptr::null()),
+    });
+    // The reference owned by the queue, once we manage to enqueue the node.
+    let me = Arc::into_raw(Arc::clone(&node)) as usize;
+    loop {
+        if !should_wait(current_state) {
+            drop(unsafe { Arc::from_raw(me as *const Waiter) });
+            return true;
+        }
+
+        node.next.set((current_state & QUEUE_MASK) as *const Waiter);
+
+        let exchange = state_and_queue.compare_exchange(
+            current_state,
+            me | (current_state & !QUEUE_MASK),
+            Ordering::Release,
+            Ordering::Relaxed,
+        );
+        if let Err(old) = exchange {
+            current_state = old;
+            continue;
+        }
+
+        return park(cell, node, give_up);
+    }
+}
+
+// Blocks until `node`, which is in the queue of `cell`, is woken up. Returns
+// `false` if it's time to `give_up` before that.
+fn park(cell: usize, node: Arc<Waiter>, give_up: GiveUp<'_>) -> bool {
+    // The node stays in the queue while we wake up to check the cancellation
+    // flag, so that polling it doesn't enqueue a node each time.
+    while !node.signaled.load(Ordering::Acquire) {
+        if give_up.due() {
+            // The node stays in the queue, and is freed by whoever wakes it
+            // up. Until then, we reuse it.
+            abandon(cell, node);
+            return false;
+        }
+        match give_up.wake_at() {
+            None => thread::park(),
+            Some(wake_at) => {
+                primitives::park_timeout(wake_at.saturating_duration_since(Instant::now()))
+            }
+        }
+    }
+    true
+}
+
+// The nodes which the current thread has left in the queues of cells, when it
+// gave up waiting on them, with the addresses of the cells. There's at most
+// one per cell, so giving up on the same cells over and over doesn't grow the
+// queues.
+#[cfg(not(loom))]
+std::thread_local! {
+    static ABANDONED: RefCell<Vec<(usize, Arc<Waiter>)>> = const { RefCell::new(Vec::new()) };
+}
+
+#[cfg(not(loom))]
+fn abandon(cell: usize, node: Arc<Waiter>) {
+    let _ = ABANDONED.try_with(|abandoned| abandoned.borrow_mut().push((cell, node)));
+}
+
+// Takes the node which the current thread has left in the queue of `cell`, if
+// it's still there.
+#[cfg(not(loom))]
+fn take_abandoned(cell: usize) -> Option<Arc<Waiter>> {
+    ABANDONED
+        .try_with(|abandoned| {
+            let mut abandoned = abandoned.borrow_mut();
+            // The nodes which have been woken up are no longer in any queue.
+            // That includes the nodes of the cells which have been dropped,
+            // so `cell` can't be the address of another cell.
+            abandoned.retain(|(_, node)| !node.signaled.load(Ordering::Acquire));
+            let idx = abandoned.iter().position(|&(it, _)| it == cell)?;
+            Some(abandoned.swap_remove(idx).1)
+        })
+        .ok()
+        .flatten()
+}
+
+// `loom` runs all the threads of a model on a single OS thread, so they would
+// all share the list. Every wait enqueues a new node instead.
+#[cfg(loom)]
+fn abandon(_cell: usize, _node: Arc<Waiter>) {}
+
+#[cfg(loom)]
+fn take_abandoned(_cell: usize) -> Option<Arc<Waiter>> {
+    None
+}
+
+// Wakes up all the threads in the queue, freeing the nodes.
+// Safety: `queue` must be the head of a queue, which is no longer reachable
+// through `state_and_queue`.
+unsafe fn wake_all(queue: usize) {
+    let mut queue = queue as *const Waiter;
+    while !queue.is_null() {
+        let node = Arc::from_raw(queue);
+        queue = node.next.get();
+        // A thread is taken out of the node only once, by the thread which
+        // dequeued it.
+        let thread = node.thread.replace(None).unwrap();
+        node.signaled.store(true, Ordering::Release);
+        thread.unpark();
+    }
+}
+
+// Copied from std, except that the nodes are reference counted.
+impl Drop for WaiterQueue<'_> {
+    fn drop(&mut self) {
+        // Clear the owner while we are still `RUNNING`: once we are not, the
+        // next initializer might store its own.
+        self.owner.store(0, Ordering::Relaxed);
+        let state_and_queue =
+            self.state_and_queue.swap(self.set_state_on_drop_to, Ordering::AcqRel);
+
+        assert_eq!(state_and_queue & STATE_MASK, RUNNING);
+
+        unsafe { wake_all(state_and_queue & QUEUE_MASK) }
+    }
+}
+
+#[test]
+#[cfg(not(loom))]
+fn test_abandoned_nodes_are_reused() {
+    fn queue_len<T>(cell: &OnceCell<T>) -> usize {
+        let mut node = (cell.state_and_queue.load(Ordering::Acquire) & QUEUE_MASK) as *const Waiter;
+        let mut len = 0;
+        while !node.is_null() {
+            len += 1;
+            node = unsafe { (*node).next.get() };
+        }
+        len
+    }
+
+    let name = CellName(|f| f.write_str("OnceCell"));
+    let cells: [OnceCell<i32>; 2] =
+        [OnceCell::new(PoisonPolicy::Retry), OnceCell::new(PoisonPolicy::Retry)];
+    for i in 0..10_000 {
+        assert_eq!(cells[i % 2].wait_timeout(name, Duration::from_secs(0)), None);
+    }
+    assert_eq!(queue_len(&cells[0]), 1);
+    assert_eq!(queue_len(&cells[1]), 1);
+
+    // The abandoned nodes are woken up with the others, and the next waits
+    // enqueue new ones.
+    cells[0].set(name, 92).unwrap();
+    assert_eq!(cells[0].wait_timeout(name, Duration::from_secs(0)), Some(&92));
+    assert_eq!(queue_len(&cells[0]), 0);
+    assert_eq!(cells[1].wait_timeout(name, Duration::from_secs(0)), None);
+    assert_eq!(queue_len(&cells[1]), 1);
 }
@@ -0,0 +1,170 @@
+//! Fixed-size tables, in which each entry is computed on first access.
+//!
+//! A table of `N` entries is stored inline as an array of `N` cells, so it
+//! needs no allocations of its own, and can be created in a `const` context.
+
+pub mod unsync {
+    use core::fmt;
+
+    use crate::unsync::OnceCell;
+
+    /// A table of `N` values, each of which is computed on first access by
+    /// calling the function with its index. Not thread safe.
+    ///
+    /// # Example
+    /// ```
+    /// use once_cell::unsync::LazyTable;
+    ///
+    /// let squares: LazyTable<u64, 16> = LazyTable::new(|i| {
+    ///     println!("computing {}", i);
+    ///     (i * i) as u64
+    /// });
+    /// assert_eq!(*squares.get(3), 9);
+    /// // Prints "computing 3" only once.
+    /// assert_eq!(*squares.get(3), 9);
+    /// assert_eq!(squares.len(), 16);
+    /// ```
+    pub struct LazyTable<T, const N: usize, F = fn(usize) -> T> {
+        cells: [OnceCell<T>; N],
+        init: F,
+    }
+
+    impl<T, const N: usize, F> LazyTable<T, N, F> {
+        // Only used to initialize the array, which can't be done with a
+        // `Copy` value, as cells aren't `Copy`.
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY: OnceCell<T> = OnceCell::new();
+
+        /// Creates a new table with the given initializing function.
+        pub const fn new(init: F) -> LazyTable<T, N, F> {
+            LazyTable { cells: [Self::EMPTY; N], init }
+        }
+
+        /// Returns the number of entries in the table.
+        pub const fn len(&self) -> usize {
+            N
+        }
+
+        /// Returns `true` if the table has no entries.
+        pub const fn is_empty(&self) -> bool {
+            N == 0
+        }
+
+        /// Gets the entry at `idx` if it is already computed. Returns `None`
+        /// if it isn't, or if `idx` is out of bounds.
+        pub fn get_if_init(&self, idx: usize) -> Option<&T> {
+            self.cells.get(idx)?.get()
+        }
+    }
+
+    impl<T, const N: usize, F: Fn(usize) -> T> LazyTable<T, N, F> {
+        /// Gets the entry at `idx`, computing it if this is the first access.
+        /// Other entries are left untouched.
+        ///
+        /// # Panics
+        ///
+        /// Panics if `idx` is out of bounds. If the initializing function
+        /// panics, the panic is propagated to the caller, and the entry stays
+        /// uninitialized.
+        pub fn get(&self, idx: usize) -> &T {
+            self.cells[idx].get_or_init(|| (self.init)(idx))
+        }
+    }
+
+    impl<T: fmt::Debug, const N: usize, F> fmt::Debug for LazyTable<T, N, F> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_list().entries(self.cells.iter()).finish()
+        }
+    }
+}
+
+#[cfg(once_cell_sync)]
+pub mod sync {
+    use core::{any::type_name, fmt};
+
+    use crate::{sync::OnceCell, CellName};
+
+    /// A thread-safe table of `N` values, each of which is computed on first
+    /// access by calling the function with its index.
+    ///
+    /// Each entry behaves like a `sync::OnceCell`: the function runs at most
+    /// once per entry, and other threads which access the same entry block
+    /// until it completes. Different entries are computed independently.
+    ///
+    /// # Example
+    /// ```
+    /// use once_cell::sync::LazyTable;
+    ///
+    /// fn decode(opcode: usize) -> String {
+    ///     format!("op{:02x}", opcode)
+    /// }
+    ///
+    /// static DECODERS: LazyTable<String, 256> = LazyTable::new(decode);
+    ///
+    /// fn main() {
+    ///     assert_eq!(DECODERS.get(0x2a), "op2a");
+    ///     assert_eq!(DECODERS.get_if_init(0x2a).map(String::as_str), Some("op2a"));
+    ///     assert_eq!(DECODERS.get_if_init(0x2b), None);
+    /// }
+    /// ```
+    pub struct LazyTable<T, const N: usize, F = fn(usize) -> T> {
+        cells: [OnceCell<T>; N],
+        init: F,
+    }
+
+    impl<T, const N: usize, F> LazyTable<T, N, F> {
+        // Only used to initialize the array, which can't be done with a
+        // `Copy` value, as cells aren't `Copy`.
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY: OnceCell<T> = OnceCell::new();
+
+        /// Creates a new table with the given initializing function.
+        pub const fn new(init: F) -> LazyTable<T, N, F> {
+            LazyTable { cells: [Self::EMPTY; N], init }
+        }
+
+        /// Returns the number of entries in the table.
+        pub const fn len(&self) -> usize {
+            N
+        }
+
+        /// Returns `true` if the table has no entries.
+        pub const fn is_empty(&self) -> bool {
+            N == 0
+        }
+
+        /// Gets the entry at `idx` if it is already computed. Returns `None`
+        /// if it isn't, or if `idx` is out of bounds. This method never
+        /// blocks.
+        pub fn get_if_init(&self, idx: usize) -> Option<&T> {
+            self.cells.get(idx)?.get()
+        }
+    }
+
+    impl<T, const N: usize, F: Fn(usize) -> T> LazyTable<T, N, F> {
+        /// Gets the entry at `idx`, computing it if this is the first access.
+        /// Other entries are left untouched.
+        ///
+        /// If another thread is computing the same entry, blocks until it
+        /// completes.
+        ///
+        /// # Panics
+        ///
+        /// Panics if `idx` is out of bounds. If the initializing function
+        /// panics, the panic is propagated to the caller, and the entry stays
+        /// uninitialized.
+        pub fn get(&self, idx: usize) -> &T {
+            self.cells[idx].get_or_init_as(Self::NAME, || (self.init)(idx))
+        }
+
+        /// Names the table in the panic of a re-entrant initialization.
+        const NAME: CellName =
+            CellName(|f| write!(f, "sync::LazyTable<{}, {}>", type_name::<T>(), N));
+    }
+
+    impl<T: fmt::Debug, const N: usize, F> fmt::Debug for LazyTable<T, N, F> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_list().entries(self.cells.iter()).finish()
+        }
+    }
+}
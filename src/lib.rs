@@ -143,13 +143,13 @@ impl Ctx {
 |`Mutex<T>`            | `MutexGuard<T>`        | may deadlock at runtime, may block the thread |
 |`sync::OnceCell<T>`   | `&T`                   | assignable only once, may block the thread    |
 
-Technically, calling `get_or_init` will also cause a panic or a deadlock if it recursively calls
-itself. However, because the assignment can happen only once, such cases should be more rare than
-equivalents with `RefCell` and `Mutex`.
+Technically, calling `get_or_init` will also cause a panic if it recursively calls itself. However,
+because the assignment can happen only once, such cases should be more rare than equivalents with
+`RefCell` and `Mutex`.
 
 # Minimum Supported `rustc` Version
 
-This crate's minimum supported `rustc` version is `1.31.1`.
+This crate's minimum supported `rustc` version is `1.63.0`, or `1.61.0` without the `std` feature.
 
 If optional features are not enabled (`default-features = false` in `Cargo.toml`),
 MSRV will be updated conservatively. When using specific features or default features, MSRV might be updated
@@ -162,11 +162,59 @@ Implementation is based on [`lazy_static`](https://github.com/rust-lang-nursery/
 [`lazy_cell`](https://github.com/indiv0/lazycell/) crates and in some sense just streamlines and
 unifies the APIs of those crates.
 
-To implement a sync flavor of `OnceCell`, this crates uses either `std::sync::Once` or
-`parking_lot::Mutex`. This is controlled by the `parking_lot` feature, which is enabled by default.
+To implement a sync flavor of `OnceCell`, this crates uses either a custom re-implementation of
+`std::sync::Once`, or a single state word on which threads park with `parking_lot_core`. This is
+controlled by the `parking_lot` feature, which is enabled by default. On Linux, the `futex` feature
+provides the same implementation without `parking_lot`, which blocks threads with the `futex` system
+call; elsewhere it has no effect. All implementations have the same observable behavior, including
+support for fallible initialization.
+
+`sync::OnceCell<T>` and `sync::Lazy<T>` are `sync::OnceCellWith<T, Builtin>` and
+`sync::LazyWith<T, Builtin>`. With the `lock_api` feature, which is enabled by default, `R` in
+`sync::OnceCellWith<T, R>` can be a user-supplied `lock_api::RawMutex` instead, which blocks the
+threads racing to run the initializer, for example, a priority-inheriting or an instrumented mutex.
 
 This crate uses unsafe.
 
+# `race`
+
+The `race` module provides `OnceNonZeroUsize`, `OnceBool` and `OnceBox`. These are "first one wins"
+cells: if several threads race to initialize one, all of them run their initializers, but exactly
+one value is published with a compare-exchange, and everybody observes it. These cells are a single
+atomic word, never block, and are independent of the `sync` implementation.
+
+# `future`
+
+With the `async` feature, the `future` module provides an `OnceCell` which is initialized by a
+future. Tasks which access the cell while another task is initializing it are suspended until the
+initializer completes, instead of blocking the executor thread. If the initializing future fails, or
+is dropped, one of the waiting tasks retries.
+
+# `serde`
+
+With the `serde` feature, `OnceCell` implements `Serialize` and `Deserialize` as an `Option<T>`, so
+structs with cached fields can derive them. `Lazy` implements `Serialize` by forcing the value.
+
+# `no_std` support
+
+The `std` feature is enabled by default. Without it, the crate is `#![no_std]` and does not need
+`alloc` either. `unsync::OnceCell`, `unsync::Lazy` and the `race` module are always available,
+except for `race::OnceBox` and `unsync::OnceVec`, which require the `alloc` feature.
+`sync::OnceCell` and `sync::Lazy` require either the `std` feature, or the `spin` feature, which
+provides an implementation that busy-waits on an atomic while another thread runs the initializer,
+or the `critical-section` feature, which runs the initializer inside a critical section from the
+[`critical-section`](https://crates.io/crates/critical-section) crate. On single-core and
+interrupt-driven targets, this usually means with interrupts disabled, so initializers should be
+short. Initializers of different cells never run concurrently with this implementation.
+`sync::OnceVec` additionally requires `alloc`. `spin` and `critical-section` are used only without
+`std`: with `std`, threads block, with `parking_lot` if it is enabled, or else on Linux with `futex`
+if it is enabled. Without `std`, `critical-section` takes precedence over `spin`.
+
+```toml
+[dependencies]
+once_cell = { version = "0.2", default-features = false, features = ["spin"] }
+```
+
 # Related crates
 
 * [double-checked-cell](https://github.com/niklasf/double-checked-cell)
@@ -177,23 +225,292 @@ This crate uses unsafe.
 
 */
 
-#[cfg(feature = "parking_lot")]
-#[path = "imp_pl.rs"]
+// `loom` needs `std`, also to model the implementations which don't.
+#![cfg_attr(not(any(feature = "std", loom)), no_std)]
+// With `--cfg loom`, only the model tests use the implementations of `sync`.
+#![cfg_attr(loom, allow(dead_code, unused_imports))]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+mod owner;
+#[cfg(any(feature = "std", feature = "spin", feature = "critical-section"))]
+mod poison;
+#[cfg(all(feature = "std", not(loom)))]
+mod once_map;
+#[cfg(feature = "alloc")]
+mod once_vec;
+mod lazy_table;
+#[cfg(all(feature = "std", not(loom)))]
+mod reloadable;
+#[cfg(all(feature = "std", not(loom)))]
+mod expiring_lazy;
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(once_cell_sync)]
+mod lock;
+
+#[cfg(any(feature = "std", feature = "spin"))]
+mod primitives;
+#[cfg(all(feature = "std", feature = "parking_lot", not(loom)))]
+#[path = "park_pl.rs"]
+mod park;
+#[cfg(all(feature = "futex", target_os = "linux", not(feature = "parking_lot"), not(loom)))]
+#[path = "park_futex.rs"]
+mod park;
+#[cfg(all(
+    any(
+        all(feature = "std", feature = "parking_lot"),
+        all(feature = "futex", target_os = "linux")
+    ),
+    loom
+))]
+#[path = "park_loom.rs"]
+mod park;
+#[cfg(any(
+    all(feature = "std", feature = "parking_lot"),
+    all(feature = "futex", target_os = "linux")
+))]
+#[path = "imp_parked.rs"]
+mod imp;
+// `spin` and `critical-section` are for targets without `std`. With `std`, it
+// always wins, so that enabling either doesn't make another crate's cells spin.
+#[cfg(all(feature = "critical-section", not(feature = "std")))]
+#[path = "imp_cs.rs"]
 mod imp;
-#[cfg(not(feature = "parking_lot"))]
+#[cfg(all(feature = "spin", not(feature = "std"), not(feature = "critical-section")))]
+#[path = "imp_spin.rs"]
+mod imp;
+#[cfg(all(
+    feature = "std",
+    not(feature = "parking_lot"),
+    not(all(feature = "futex", target_os = "linux"))
+))]
 #[path = "imp_std.rs"]
 mod imp;
+#[cfg(all(test, loom))]
+mod loom_tests;
+
+/// The state of a `OnceCell`, as returned by `state`.
+///
+/// The state of a `sync::OnceCell` can change concurrently, so it is only a
+/// snapshot, which is useful for diagnostics, like health checks or debugging
+/// deadlocks.
+///
+/// This type requires the `std` feature.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellState {
+    /// The cell is empty, and nobody is initializing it.
+    Uninit,
+    /// The initializing function is running on `thread`.
+    Initializing {
+        /// The thread which runs the initializing function.
+        thread: std::thread::ThreadId,
+    },
+    /// The cell holds a value.
+    Ready,
+    /// The initializing function has panicked, and the cell is poisoned.
+    /// See `policy::PoisonPolicy`.
+    Poisoned,
+}
+
+/// The error returned by `sync::OnceCell::get_or_try_init_timeout` and
+/// `sync::OnceCell::get_or_try_init_cancellable`.
+///
+/// `Timeout` and `Cancelled` mean that the current thread gave up waiting for
+/// another thread, which is running its initializing function. That function
+/// keeps running, and may still initialize the cell.
+///
+/// This type requires the `std` feature.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitError<E> {
+    /// The timeout has passed while another thread was initializing the cell.
+    Timeout,
+    /// The cancellation flag was set while another thread was initializing
+    /// the cell.
+    Cancelled,
+    /// The initializing function of the current thread has failed.
+    Init(E),
+}
+
+#[cfg(feature = "std")]
+impl<E: core::fmt::Display> core::fmt::Display for WaitError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WaitError::Timeout => f.write_str("timed out waiting for the cell to be initialized"),
+            WaitError::Cancelled => f.write_str("cancelled waiting for the cell to be initialized"),
+            WaitError::Init(err) => err.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for WaitError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WaitError::Init(err) => err.source(),
+            _ => None,
+        }
+    }
+}
+
+/// When a thread which waits for another thread to initialize a cell gives
+/// up: at the `deadline`, or once `cancel` is set, whichever comes first.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy)]
+pub(crate) struct GiveUp<'a> {
+    pub(crate) deadline: Option<std::time::Instant>,
+    pub(crate) cancel: Option<&'a std::sync::atomic::AtomicBool>,
+}
+
+#[cfg(feature = "std")]
+impl GiveUp<'_> {
+    /// Waits for as long as it takes.
+    pub(crate) const NEVER: GiveUp<'static> = GiveUp { deadline: None, cancel: None };
+
+    /// How often a waiting thread wakes up to check the cancellation flag.
+    /// Nobody wakes it up when the flag is set.
+    const CANCEL_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
+
+    /// Whether it's time to give up.
+    pub(crate) fn due(&self) -> bool {
+        self.cancelled()
+            || match self.deadline {
+                Some(deadline) => std::time::Instant::now() >= deadline,
+                None => false,
+            }
+    }
+
+    /// Whether the cancellation flag is set.
+    pub(crate) fn cancelled(&self) -> bool {
+        match self.cancel {
+            Some(cancel) => cancel.load(std::sync::atomic::Ordering::Relaxed),
+            None => false,
+        }
+    }
+
+    /// When a waiting thread must wake up to check `due` again, if ever.
+    pub(crate) fn wake_at(&self) -> Option<std::time::Instant> {
+        let poll = self.cancel.map(|_| std::time::Instant::now() + Self::CANCEL_POLL_INTERVAL);
+        match (self.deadline, poll) {
+            (Some(deadline), Some(poll)) => Some(deadline.min(poll)),
+            (deadline, poll) => deadline.or(poll),
+        }
+    }
+}
+
+/// Names the type of a `sync` cell, like `sync::Lazy<i32>`, in the panic of a
+/// re-entrant initialization. The wrappers of `sync::OnceCell` pass their own
+/// names down, so the panic names the type the user actually accessed.
+#[cfg(any(feature = "std", feature = "spin", feature = "critical-section"))]
+#[derive(Clone, Copy)]
+pub(crate) struct CellName(pub(crate) fn(&mut core::fmt::Formatter<'_>) -> core::fmt::Result);
+
+#[cfg(any(feature = "std", feature = "spin", feature = "critical-section"))]
+impl core::fmt::Display for CellName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        (self.0)(f)
+    }
+}
+
+/// Error handling policies for `TryLazy` and `sync::OnceCell`.
+pub mod policy {
+    /// Policy for a `TryLazy` which doesn't remember failures: if the
+    /// initializing function returns an error, it is called again on the next
+    /// access. This is the default.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct Retry;
+
+    /// Policy for a `TryLazy` which remembers the first failure: once the
+    /// initializing function returns an error, it is never called again, and
+    /// all subsequent accesses return a clone of that error.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct CacheError;
+
+    /// The policy of a `TryLazy` whose errors would be kept in a cell `C`.
+    /// It decides whether the `TryLazy` needs room for the cell at all:
+    /// `Retry` doesn't, and `CacheError` does.
+    ///
+    /// This trait is implemented by `Retry` and `CacheError`, and can't be
+    /// implemented outside of this crate.
+    pub trait TryLazyPolicy<C>: sealed::Sealed {
+        #[doc(hidden)]
+        type Errors;
+        #[doc(hidden)]
+        const NO_ERRORS: Self::Errors;
+    }
+
+    impl<C> TryLazyPolicy<C> for Retry {
+        type Errors = ();
+        const NO_ERRORS: () = ();
+    }
+
+    mod sealed {
+        pub trait Sealed {}
+
+        impl Sealed for super::Retry {}
+        impl Sealed for super::CacheError {}
+    }
+
+    /// What happens to a `sync::OnceCell` if its initializing function
+    /// panics.
+    ///
+    /// In all cases, the panic is propagated to the caller which ran the
+    /// initializer. The policy determines what happens to the later callers.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum PoisonPolicy {
+        /// The cell stays empty, and the next caller of `get_or_init` runs its
+        /// own initializing function. This is the default.
+        Retry,
+        /// The cell becomes poisoned: `set`, `get_or_init` and friends panic,
+        /// until the poison is cleared with `clear_poison`.
+        Poison,
+        /// Like `Poison`, but the payload of the panic is kept, out of line,
+        /// until it is retrieved with `take_panic_payload`. The caller
+        /// which ran the initializer gets a copy of the payload, if it is a
+        /// `&'static str` or a `String`, which is what `panic!` produces.
+        /// Other payloads can't be copied, so the caller gets a `&'static str`
+        /// which points to `take_panic_payload` instead.
+        ///
+        /// This variant requires the `std` feature.
+        #[cfg(feature = "std")]
+        StorePayload,
+    }
+
+    // Not derived: `#[default]` on enum variants needs a newer `rustc`.
+    #[allow(clippy::derivable_impls)]
+    impl Default for PoisonPolicy {
+        fn default() -> PoisonPolicy {
+            PoisonPolicy::Retry
+        }
+    }
+}
 
 pub mod unsync {
-    use std::{
+    use core::{
+        any::type_name,
+        cmp, fmt,
+        hash::{Hash, Hasher},
         ops::Deref,
-        cell::UnsafeCell,
-        panic::{UnwindSafe, RefUnwindSafe},
+        cell::{Cell, UnsafeCell},
+        marker::PhantomData,
     };
 
+    #[cfg(feature = "std")]
+    use crate::CellState;
+    use crate::policy::{CacheError, Retry, TryLazyPolicy};
+    use core::panic::{UnwindSafe, RefUnwindSafe};
+
+    pub use crate::lazy_table::unsync::LazyTable;
+    #[cfg(feature = "alloc")]
+    pub use crate::once_vec::unsync::OnceVec;
+
     /// A cell which can be written to only once. Not thread safe.
     ///
-    /// Unlike `::std::cell::RefCell`, a `OnceCell` provides simple `&`
+    /// Unlike `core::cell::RefCell`, a `OnceCell` provides simple `&`
     /// references to the contents.
     ///
     /// # Example
@@ -209,15 +526,26 @@ pub mod unsync {
     /// assert_eq!(value, "Hello, World!");
     /// assert!(cell.get().is_some());
     /// ```
-    #[derive(Debug)]
     pub struct OnceCell<T> {
-        // Invariant: written to at most once.
+        // Invariant: written to at most once through a shared reference.
         inner: UnsafeCell<Option<T>>,
+        // Set while the initializer runs, to detect re-entrant initialization.
+        running: Cell<bool>,
     }
 
     impl<T: RefUnwindSafe + UnwindSafe> RefUnwindSafe for OnceCell<T> {}
     impl<T: UnwindSafe> UnwindSafe for OnceCell<T> {}
 
+    impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self.get() {
+                Some(value) => f.debug_tuple("OnceCell").field(value).finish(),
+                None if self.running.get() => f.write_str("OnceCell(<initializing>)"),
+                None => f.write_str("OnceCell(<uninit>)"),
+            }
+        }
+    }
+
     impl<T> Default for OnceCell<T> {
         fn default() -> Self {
             Self::new()
@@ -243,16 +571,64 @@ pub mod unsync {
         }
     }
 
+    impl<T: Eq> Eq for OnceCell<T> {}
+
+    /// Cells are ordered like `Option`s: an empty cell is less than any
+    /// initialized one.
+    impl<T: PartialOrd> PartialOrd for OnceCell<T> {
+        fn partial_cmp(&self, other: &OnceCell<T>) -> Option<cmp::Ordering> {
+            self.get().partial_cmp(&other.get())
+        }
+    }
+
+    impl<T: Ord> Ord for OnceCell<T> {
+        fn cmp(&self, other: &OnceCell<T>) -> cmp::Ordering {
+            self.get().cmp(&other.get())
+        }
+    }
+
+    /// Hashes like `Option<&T>`, consistently with `PartialEq`.
+    ///
+    /// Initializing an empty cell changes its hash, so don't initialize
+    /// cells which are used as keys of a `HashMap` or a `HashSet`.
+    impl<T: Hash> Hash for OnceCell<T> {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.get().hash(state)
+        }
+    }
+
+    /// Formats the value, or `<uninit>` if the cell is empty.
+    impl<T: fmt::Display> fmt::Display for OnceCell<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self.get() {
+                Some(value) => fmt::Display::fmt(value, f),
+                None => f.write_str("<uninit>"),
+            }
+        }
+    }
+
+    impl<T> From<Option<T>> for OnceCell<T> {
+        fn from(value: Option<T>) -> OnceCell<T> {
+            OnceCell { inner: UnsafeCell::new(value), running: Cell::new(false) }
+        }
+    }
+
+    impl<T> From<OnceCell<T>> for Option<T> {
+        fn from(cell: OnceCell<T>) -> Option<T> {
+            cell.into_inner()
+        }
+    }
+
     impl<T> From<T> for OnceCell<T> {
         fn from(value: T) -> Self {
-            OnceCell { inner: UnsafeCell::new(Some(value)) }
+            OnceCell { inner: UnsafeCell::new(Some(value)), running: Cell::new(false) }
         }
     }
 
     impl<T> OnceCell<T> {
         /// Creates a new empty cell.
         pub const fn new() -> OnceCell<T> {
-            OnceCell { inner: UnsafeCell::new(None) }
+            OnceCell { inner: UnsafeCell::new(None), running: Cell::new(false) }
         }
 
         /// Gets the reference to the underlying value. Returns `None`
@@ -262,6 +638,54 @@ pub mod unsync {
             unsafe { &*self.inner.get() }.as_ref()
         }
 
+        /// Returns the current state of the cell. Unlike `get`, this can
+        /// tell an empty cell from one which is being initialized, that is,
+        /// accessed from its own initializing function.
+        ///
+        /// This method requires the `std` feature.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::{unsync::OnceCell, CellState};
+        ///
+        /// let cell = OnceCell::new();
+        /// assert_eq!(cell.state(), CellState::Uninit);
+        /// cell.get_or_init(|| {
+        ///     let thread = std::thread::current().id();
+        ///     assert_eq!(cell.state(), CellState::Initializing { thread });
+        ///     92
+        /// });
+        /// assert_eq!(cell.state(), CellState::Ready);
+        /// ```
+        #[cfg(feature = "std")]
+        pub fn state(&self) -> CellState {
+            if self.get().is_some() {
+                CellState::Ready
+            } else if self.running.get() {
+                CellState::Initializing { thread: std::thread::current().id() }
+            } else {
+                CellState::Uninit
+            }
+        }
+
+        /// Gets the mutable reference to the underlying value.
+        /// Returns `None` if the cell is empty.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::unsync::OnceCell;
+        ///
+        /// let mut cell: OnceCell<u32> = OnceCell::new();
+        /// assert_eq!(cell.get_mut(), None);
+        /// cell.set(92).unwrap();
+        /// *cell.get_mut().unwrap() += 1;
+        /// assert_eq!(cell.get(), Some(&93));
+        /// ```
+        pub fn get_mut(&mut self) -> Option<&mut T> {
+            // Safe because we have unique access
+            unsafe { &mut *self.inner.get() }.as_mut()
+        }
+
         /// Sets the contents of this cell to `value`. Returns
         /// `Ok(())` if the cell was empty and `Err(value)` if it was
         /// full.
@@ -283,7 +707,8 @@ pub mod unsync {
             if slot.is_some() {
                 return Err(value);
             }
-            let slot = unsafe { &mut*self.inner.get() };
+            self.check_reentrancy();
+            let slot = unsafe { &mut *self.inner.get() };
             // This is the only place where we set the slot, no races
             // due to reentrancy/concurrency are possible, and we've
             // checked that slot is currently `None`, so this write
@@ -350,11 +775,67 @@ pub mod unsync {
             if let Some(val) = self.get() {
                 return Ok(val);
             }
-            let val = f()?;
-            assert!(self.set(val).is_ok(), "reentrant init");
+            self.check_reentrancy();
+            let val = {
+                let _running = Running::enter(&self.running);
+                f()?
+            };
+            // `f` can't fill the cell: that would have been detected as
+            // re-entrant initialization.
+            assert!(self.set(val).is_ok());
             Ok(self.get().unwrap())
         }
 
+        /// Gets the mutable reference to the contents of the cell,
+        /// initializing it with `f` if the cell was empty.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::unsync::OnceCell;
+        ///
+        /// let mut cell = OnceCell::new();
+        /// let value = cell.get_or_init_mut(|| vec![1, 2]);
+        /// value.push(3);
+        /// assert_eq!(cell.get_or_init_mut(|| unreachable!()), &vec![1, 2, 3]);
+        /// ```
+        pub fn get_or_init_mut<F: FnOnce() -> T>(&mut self, f: F) -> &mut T {
+            if self.get_mut().is_none() {
+                let value = f();
+                // `&mut self` guarantees that nobody could have filled the
+                // cell while `f` was running.
+                assert!(self.set(value).is_ok());
+            }
+            match self.get_mut() {
+                Some(value) => value,
+                None => unreachable!(),
+            }
+        }
+
+        /// Takes the value out of this `OnceCell`, moving it back to an uninitialized state.
+        ///
+        /// Has no effect and returns `None` if the `OnceCell` hasn't been initialized.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use once_cell::unsync::OnceCell;
+        ///
+        /// let mut cell: OnceCell<String> = OnceCell::new();
+        /// assert_eq!(cell.take(), None);
+        ///
+        /// let mut cell = OnceCell::new();
+        /// cell.set("hello".to_string()).unwrap();
+        /// assert_eq!(cell.take(), Some("hello".to_string()));
+        /// assert_eq!(cell.get(), None);
+        ///
+        /// cell.set("world".to_string()).unwrap();
+        /// assert_eq!(cell.get().map(String::as_str), Some("world"));
+        /// ```
+        pub fn take(&mut self) -> Option<T> {
+            // Safe because we have unique access
+            unsafe { &mut *self.inner.get() }.take()
+        }
+
         /// Consumes the `OnceCell`, returning the wrapped value. Returns
         /// `None` if the cell was empty.
         ///
@@ -375,6 +856,33 @@ pub mod unsync {
             // that it is not currently borrowed. So it is safe to move out `Option<T>`.
             self.inner.into_inner()
         }
+
+        fn check_reentrancy(&self) {
+            if self.running.get() {
+                panic!(
+                    "reentrant init of `unsync::OnceCell<{}>`: the initializer accessed the cell itself",
+                    type_name::<T>()
+                )
+            }
+        }
+    }
+
+    /// Marks a cell as being initialized, until dropped.
+    struct Running<'a> {
+        running: &'a Cell<bool>,
+    }
+
+    impl<'a> Running<'a> {
+        fn enter(running: &'a Cell<bool>) -> Running<'a> {
+            running.set(true);
+            Running { running }
+        }
+    }
+
+    impl Drop for Running<'_> {
+        fn drop(&mut self) {
+            self.running.set(false);
+        }
     }
 
     /// A value which is initialized on the first access.
@@ -397,10 +905,33 @@ pub mod unsync {
     /// //   92
     /// //   92
     /// ```
-    #[derive(Debug)]
+    ///
+    /// The initializing function is called at most once, so it can move
+    /// captured values into the result. It is dropped right after the call.
+    ///
+    /// # Panics
+    ///
+    /// If the initializing function panics, the panic is propagated to the
+    /// caller, and the `Lazy` becomes poisoned: the function can't be called
+    /// again, so all subsequent accesses panic as well.
     pub struct Lazy<T, F = fn() -> T> {
         cell: OnceCell<T>,
-        init: F,
+        init: Cell<Option<F>>,
+    }
+
+    impl<T, F: RefUnwindSafe> RefUnwindSafe for Lazy<T, F> where OnceCell<T>: RefUnwindSafe {}
+
+    impl<T: fmt::Debug, F> fmt::Debug for Lazy<T, F> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Lazy").field("cell", &self.cell).field("init", &"..").finish()
+        }
+    }
+
+    impl<T: Default> Default for Lazy<T> {
+        /// Creates a new lazy value, which is initialized with `T::default()`.
+        fn default() -> Lazy<T> {
+            Lazy::new(T::default)
+        }
     }
 
     impl<T, F> Lazy<T, F> {
@@ -420,11 +951,79 @@ pub mod unsync {
         /// # }
         /// ```
         pub const fn new(init: F) -> Lazy<T, F> {
-            Lazy { cell: OnceCell::new(), init }
+            Lazy { cell: OnceCell::new(), init: Cell::new(Some(init)) }
+        }
+
+        /// Gets the reference to the result of this lazy value if
+        /// it was initialized, otherwise returns `None`. Unlike `force`,
+        /// this never runs the initializing function.
+        ///
+        /// This is an associated function rather than a method, so that it
+        /// doesn't shadow methods of `T` through `Deref`.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::unsync::Lazy;
+        ///
+        /// let lazy = Lazy::new(|| 92);
+        ///
+        /// assert_eq!(Lazy::get(&lazy), None);
+        /// assert_eq!(&*lazy, &92);
+        /// assert_eq!(Lazy::get(&lazy), Some(&92));
+        /// ```
+        pub fn get(this: &Lazy<T, F>) -> Option<&T> {
+            this.cell.get()
+        }
+
+        /// Gets the mutable reference to the result of this lazy value if
+        /// it was initialized, otherwise returns `None`. This never runs the
+        /// initializing function.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::unsync::Lazy;
+        ///
+        /// let mut lazy = Lazy::new(|| 92);
+        ///
+        /// assert_eq!(Lazy::get_mut(&mut lazy), None);
+        /// assert_eq!(*lazy, 92);
+        /// *Lazy::get_mut(&mut lazy).unwrap() += 1;
+        /// assert_eq!(*lazy, 93);
+        /// ```
+        pub fn get_mut(this: &mut Lazy<T, F>) -> Option<&mut T> {
+            this.cell.get_mut()
+        }
+
+        /// Consumes this lazy value, returning the result if it was
+        /// initialized, or the initializing function if it never ran.
+        ///
+        /// # Panics
+        ///
+        /// Panics if the `Lazy` is poisoned, that is, if the initializing
+        /// function was called, but panicked.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::unsync::Lazy;
+        ///
+        /// let hello = "Hello, World!".to_string();
+        /// let lazy: Lazy<String, _> = Lazy::new(|| hello.to_uppercase());
+        /// let init = Lazy::into_value(lazy).unwrap_err();
+        /// assert_eq!(init(), "HELLO, WORLD!");
+        ///
+        /// let lazy = Lazy::new(|| 92);
+        /// assert_eq!(*lazy, 92);
+        /// assert_eq!(Lazy::into_value(lazy).ok(), Some(92));
+        /// ```
+        pub fn into_value(this: Lazy<T, F>) -> Result<T, F> {
+            let Lazy { cell, init } = this;
+            cell.into_inner().ok_or_else(|| {
+                init.take().unwrap_or_else(|| panic!("Lazy instance has previously been poisoned"))
+            })
         }
     }
 
-    impl<T, F: Fn() -> T> Lazy<T, F> {
+    impl<T, F: FnOnce() -> T> Lazy<T, F> {
         /// Forces the evaluation of this lazy value and
         /// returns a reference to result. This is equivalent
         /// to the `Deref` impl, but is explicit.
@@ -439,20 +1038,182 @@ pub mod unsync {
         /// assert_eq!(&*lazy, &92);
         /// ```
         pub fn force(this: &Lazy<T, F>) -> &T {
-            this.cell.get_or_init(|| (this.init)())
+            this.cell.get_or_init(|| match this.init.take() {
+                Some(f) => f(),
+                None => panic!("Lazy instance has previously been poisoned"),
+            })
         }
     }
 
-    impl<T, F: Fn() -> T> Deref for Lazy<T, F> {
+    impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
         type Target = T;
         fn deref(&self) -> &T {
             Lazy::force(self)
         }
     }
+
+    /// A value which is initialized on the first access, by a function which
+    /// can fail.
+    ///
+    /// This is a `Lazy` for values like configuration or connection pools,
+    /// where the initializing function returns a `Result`: `force` returns
+    /// the error to the caller instead of panicking.
+    ///
+    /// With the default `Retry` policy, a failed initialization leaves the
+    /// `TryLazy` empty, and the next access calls the function again. With
+    /// the `CacheError` policy, the first error is stored, and all subsequent
+    /// accesses return a clone of it without calling the function. Because
+    /// the function may be called more than once, it must be `Fn`.
+    ///
+    /// # Example
+    /// ```
+    /// use std::{cell::Cell, num::ParseIntError};
+    ///
+    /// use once_cell::{policy::CacheError, unsync::TryLazy};
+    ///
+    /// let input = Cell::new("eighty");
+    /// let port: TryLazy<u16, ParseIntError, _> = TryLazy::new(|| input.get().parse());
+    /// assert!(port.force().is_err());
+    ///
+    /// // The error is not cached, so the next access tries again.
+    /// input.set("80");
+    /// assert_eq!(port.force(), Ok(&80));
+    ///
+    /// input.set("eighty");
+    /// let port: TryLazy<u16, ParseIntError, _, CacheError> = TryLazy::new(|| input.get().parse());
+    /// assert!(port.force().is_err());
+    ///
+    /// // The error is cached, and the function is not called again.
+    /// input.set("80");
+    /// assert!(port.force().is_err());
+    /// assert!(port.error().is_some());
+    /// ```
+    pub struct TryLazy<T, E, F = fn() -> Result<T, E>, P: TryLazyPolicy<OnceCell<E>> = Retry> {
+        cell: OnceCell<T>,
+        error: P::Errors,
+        init: F,
+        policy: PhantomData<P>,
+    }
+
+    impl<E> TryLazyPolicy<OnceCell<E>> for CacheError {
+        type Errors = OnceCell<E>;
+        #[allow(clippy::declare_interior_mutable_const)]
+        const NO_ERRORS: OnceCell<E> = OnceCell::new();
+    }
+
+    impl<T, E, F, P> fmt::Debug for TryLazy<T, E, F, P>
+    where
+        T: fmt::Debug,
+        P: TryLazyPolicy<OnceCell<E>>,
+        P::Errors: fmt::Debug,
+    {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("TryLazy")
+                .field("cell", &self.cell)
+                .field("error", &self.error)
+                .field("init", &"..")
+                .finish()
+        }
+    }
+
+    impl<T, E, F, P: TryLazyPolicy<OnceCell<E>>> TryLazy<T, E, F, P> {
+        /// Creates a new lazy value with the given initializing function.
+        pub const fn new(init: F) -> TryLazy<T, E, F, P> {
+            TryLazy { cell: OnceCell::new(), error: P::NO_ERRORS, init, policy: PhantomData }
+        }
+
+        /// Gets the reference to the result of this lazy value if it was
+        /// successfully initialized, without calling the initializing
+        /// function.
+        pub fn get(&self) -> Option<&T> {
+            self.cell.get()
+        }
+    }
+
+    impl<T, E, F> TryLazy<T, E, F, Retry> {
+        /// Always returns `None`: under the `Retry` policy, errors are not
+        /// cached.
+        pub fn error(&self) -> Option<&E> {
+            None
+        }
+    }
+
+    impl<T, E, F> TryLazy<T, E, F, CacheError> {
+        /// Gets the reference to the cached error, if the initializing
+        /// function has failed.
+        pub fn error(&self) -> Option<&E> {
+            self.error.get()
+        }
+    }
+
+    impl<T, E, F: Fn() -> Result<T, E>> TryLazy<T, E, F, Retry> {
+        /// Forces the evaluation of this lazy value and returns a reference
+        /// to the result, or the error returned by the initializing function.
+        ///
+        /// If the value was not initialized yet, calls the initializing
+        /// function, even if it has failed before.
+        pub fn force(&self) -> Result<&T, E> {
+            self.cell.get_or_try_init(&self.init)
+        }
+    }
+
+    impl<T, E: Clone, F: Fn() -> Result<T, E>> TryLazy<T, E, F, CacheError> {
+        /// Forces the evaluation of this lazy value and returns a reference
+        /// to the result, or the error returned by the initializing function.
+        ///
+        /// If the initializing function has failed before, returns a clone of
+        /// that error without calling the function again.
+        pub fn force(&self) -> Result<&T, E> {
+            if let Some(err) = self.error.get() {
+                return Err(err.clone());
+            }
+            self.cell.get_or_try_init(|| {
+                let res = (self.init)();
+                if let Err(err) = &res {
+                    let _ = self.error.set(err.clone());
+                }
+                res
+            })
+        }
+    }
 }
 
+pub mod race;
+
+#[cfg(all(feature = "async", not(loom)))]
+pub mod future;
+
+#[cfg(once_cell_sync)]
 pub mod sync {
-    use crate::imp::OnceCell as Imp;
+    use core::{
+        any::type_name,
+        cell::Cell,
+        cmp, fmt,
+        hash::{Hash, Hasher},
+        marker::PhantomData,
+        ops::Deref,
+    };
+    use core::panic::RefUnwindSafe;
+
+    #[cfg(feature = "std")]
+    use crate::{CellState, WaitError};
+    use crate::{
+        imp::OnceCell as Imp,
+        lock::Guard as LockGuard,
+        CellName,
+        policy::{CacheError, PoisonPolicy, Retry, TryLazyPolicy},
+    };
+
+    #[cfg(feature = "std")]
+    pub use crate::once_map::OnceMap;
+    #[cfg(feature = "alloc")]
+    pub use crate::once_vec::sync::OnceVec;
+    pub use crate::lazy_table::sync::LazyTable;
+    pub use crate::lock::{Builtin, Lock};
+    #[cfg(feature = "std")]
+    pub use crate::reloadable::{Reloadable, ReloadableGuard};
+    #[cfg(feature = "std")]
+    pub use crate::expiring_lazy::{Clock, ExpiringLazy, MonotonicClock};
 
     /// A thread-safe cell which can be written to only once.
     ///
@@ -477,18 +1238,111 @@ pub mod sync {
     /// assert!(value.is_some());
     /// assert_eq!(value.unwrap().as_str(), "Hello, World!");
     /// ```
-    #[derive(Debug)]
-    pub struct OnceCell<T>(Imp<T>);
-
-    impl<T> Default for OnceCell<T> {
-        fn default() -> OnceCell<T> {
-            OnceCell::new()
-        }
+    ///
+    /// # Poisoning
+    ///
+    /// By default, if the initializing function panics, the cell stays empty,
+    /// and the next caller runs its own function. A cell can be created with
+    /// a different `PoisonPolicy` instead, which makes it unusable after such
+    /// a panic, until the poison is explicitly cleared. Poisoning works the
+    /// same way with all implementations of `sync`.
+    pub type OnceCell<T> = OnceCellWith<T, Builtin>;
+
+    /// A thread-safe cell which can be written to only once, like
+    /// `OnceCell`, and which holds the lock `R` while it runs the
+    /// initializing function.
+    ///
+    /// `OnceCell<T>` is `OnceCellWith<T, Builtin>`. With the `lock_api`
+    /// feature, `R` can be any `lock_api::RawMutex` instead: the threads
+    /// which race to initialize the cell block on the mutex, so the cell
+    /// inherits the properties of the mutex, like priority inheritance or
+    /// instrumentation. The threads which `wait` for the value, or which give
+    /// up after a timeout, block like they do with `OnceCell`. Once the cell
+    /// is initialized, reading it never touches the mutex.
+    ///
+    /// Without the `std` feature, there's no way to identify the current
+    /// thread, and a re-entrant initialization deadlocks on the mutex, unless
+    /// `R` is a re-entrant mutex.
+    ///
+    /// # Example
+    /// ```
+    /// # #[cfg(feature = "lock_api")] {
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// use lock_api::{GuardSend, RawMutex};
+    /// use once_cell::sync::OnceCellWith;
+    ///
+    /// /// A minimal spin lock, in place of a real-time or an instrumented mutex.
+    /// struct SpinLock(AtomicBool);
+    ///
+    /// unsafe impl RawMutex for SpinLock {
+    ///     const INIT: SpinLock = SpinLock(AtomicBool::new(false));
+    ///     type GuardMarker = GuardSend;
+    ///
+    ///     fn lock(&self) {
+    ///         while !self.try_lock() {
+    ///             std::hint::spin_loop();
+    ///         }
+    ///     }
+    ///
+    ///     fn try_lock(&self) -> bool {
+    ///         self.0.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok()
+    ///     }
+    ///
+    ///     fn unlock(&self) {
+    ///         self.0.store(false, Ordering::Release);
+    ///     }
+    /// }
+    ///
+    /// static CELL: OnceCellWith<String, SpinLock> = OnceCellWith::new();
+    ///
+    /// std::thread::spawn(|| {
+    ///     let value: &String = CELL.get_or_init(|| "Hello, World!".to_string());
+    ///     assert_eq!(value, "Hello, World!");
+    /// }).join().unwrap();
+    ///
+    /// assert_eq!(CELL.get().map(String::as_str), Some("Hello, World!"));
+    /// # }
+    /// ```
+    pub struct OnceCellWith<T, R> {
+        lock: R,
+        inner: Imp<T>,
     }
 
-    impl<T: Clone> Clone for OnceCell<T> {
-        fn clone(&self) -> OnceCell<T> {
-            let res = OnceCell::new();
+    impl<T: fmt::Debug, R: Lock> fmt::Debug for OnceCellWith<T, R> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = if R::BUILTIN { "OnceCell" } else { "OnceCellWith" };
+            if let Some(value) = self.get() {
+                return f.debug_tuple(name).field(value).finish();
+            }
+            #[cfg(feature = "std")]
+            match self.state() {
+                CellState::Initializing { thread } => {
+                    write!(f, "{}(<initializing by {:?}>)", name, thread)
+                }
+                CellState::Poisoned => write!(f, "{}(<poisoned>)", name),
+                _ => write!(f, "{}(<uninit>)", name),
+            }
+            #[cfg(not(feature = "std"))]
+            {
+                if self.is_poisoned() {
+                    write!(f, "{}(<poisoned>)", name)
+                } else {
+                    write!(f, "{}(<uninit>)", name)
+                }
+            }
+        }
+    }
+
+    impl<T, R: Lock> Default for OnceCellWith<T, R> {
+        fn default() -> OnceCellWith<T, R> {
+            OnceCellWith::new()
+        }
+    }
+
+    impl<T: Clone, R: Lock> Clone for OnceCellWith<T, R> {
+        fn clone(&self) -> OnceCellWith<T, R> {
+            let res = OnceCellWith::with_poison_policy(self.inner.policy());
             if let Some(value) = self.get() {
                 match res.set(value.clone()) {
                     Ok(()) => (),
@@ -499,7 +1353,7 @@ pub mod sync {
         }
     }
 
-    impl<T> From<T> for OnceCell<T> {
+    impl<T, R: Lock> From<T> for OnceCellWith<T, R> {
         fn from(value: T) -> Self {
             let cell = Self::new();
             cell.get_or_init(|| value);
@@ -507,29 +1361,265 @@ pub mod sync {
         }
     }
 
-    impl<T: PartialEq> PartialEq for OnceCell<T> {
-        fn eq(&self, other: &OnceCell<T>) -> bool {
+    impl<T: PartialEq, R: Lock> PartialEq for OnceCellWith<T, R> {
+        fn eq(&self, other: &OnceCellWith<T, R>) -> bool {
             self.get() == other.get()
         }
     }
 
-    impl<T> OnceCell<T> {
+    impl<T: Eq, R: Lock> Eq for OnceCellWith<T, R> {}
+
+    /// Cells are ordered like `Option`s: an empty cell is less than any
+    /// initialized one.
+    impl<T: PartialOrd, R: Lock> PartialOrd for OnceCellWith<T, R> {
+        fn partial_cmp(&self, other: &OnceCellWith<T, R>) -> Option<cmp::Ordering> {
+            self.get().partial_cmp(&other.get())
+        }
+    }
+
+    impl<T: Ord, R: Lock> Ord for OnceCellWith<T, R> {
+        fn cmp(&self, other: &OnceCellWith<T, R>) -> cmp::Ordering {
+            self.get().cmp(&other.get())
+        }
+    }
+
+    /// Hashes like `Option<&T>`, consistently with `PartialEq`.
+    ///
+    /// Initializing an empty cell changes its hash, so don't initialize
+    /// cells which are used as keys of a `HashMap` or a `HashSet`.
+    impl<T: Hash, R: Lock> Hash for OnceCellWith<T, R> {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.get().hash(state)
+        }
+    }
+
+    /// Formats the value, or `<uninit>` if the cell is empty.
+    impl<T: fmt::Display, R: Lock> fmt::Display for OnceCellWith<T, R> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self.get() {
+                Some(value) => fmt::Display::fmt(value, f),
+                None => f.write_str("<uninit>"),
+            }
+        }
+    }
+
+    impl<T, R: Lock> From<Option<T>> for OnceCellWith<T, R> {
+        fn from(value: Option<T>) -> OnceCellWith<T, R> {
+            let cell = OnceCellWith::new();
+            if let Some(value) = value {
+                cell.get_or_init(|| value);
+            }
+            cell
+        }
+    }
+
+    impl<T, R: Lock> From<OnceCellWith<T, R>> for Option<T> {
+        fn from(cell: OnceCellWith<T, R>) -> Option<T> {
+            cell.into_inner()
+        }
+    }
+
+    impl<T, R: Lock> OnceCellWith<T, R> {
         /// Creates a new empty cell.
-        pub const fn new() -> OnceCell<T> {
-            OnceCell(Imp::new())
+        pub const fn new() -> OnceCellWith<T, R> {
+            OnceCellWith::with_poison_policy(PoisonPolicy::Retry)
+        }
+
+        /// Creates a new empty cell, which handles panics of the
+        /// initializing function according to `policy`.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::{policy::PoisonPolicy, sync::OnceCell};
+        ///
+        /// static CELL: OnceCell<u32> = OnceCell::with_poison_policy(PoisonPolicy::Poison);
+        ///
+        /// fn main() {
+        ///     let res = std::panic::catch_unwind(|| CELL.get_or_init(|| panic!("kaboom")));
+        ///     assert!(res.is_err());
+        ///     assert!(CELL.is_poisoned());
+        ///
+        ///     // Poisoned cell panics on every attempt to initialize it.
+        ///     let res = std::panic::catch_unwind(|| CELL.get_or_init(|| 92));
+        ///     assert!(res.is_err());
+        ///
+        ///     CELL.clear_poison();
+        ///     assert_eq!(CELL.get_or_init(|| 92), &92);
+        /// }
+        /// ```
+        pub const fn with_poison_policy(policy: PoisonPolicy) -> OnceCellWith<T, R> {
+            OnceCellWith { lock: R::INIT, inner: Imp::new(policy) }
+        }
+
+        /// Returns `true` if an initializing function of this cell has
+        /// panicked, and the cell's `PoisonPolicy` is not `Retry`.
+        pub fn is_poisoned(&self) -> bool {
+            self.inner.is_poisoned()
+        }
+
+        /// Clears the poison, so that the cell can be initialized again.
+        /// Drops the stored panic payload, if any.
+        pub fn clear_poison(&self) {
+            self.inner.clear_poison()
+        }
+
+        /// Takes the payload of the panic which poisoned this cell, if the
+        /// cell uses `PoisonPolicy::StorePayload`. The cell stays poisoned.
+        ///
+        /// This method requires the `std` feature.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::{policy::PoisonPolicy, sync::OnceCell};
+        ///
+        /// let cell: OnceCell<u32> = OnceCell::with_poison_policy(PoisonPolicy::StorePayload);
+        /// let res = std::panic::catch_unwind(|| cell.get_or_init(|| panic!("kaboom")));
+        /// assert!(res.is_err());
+        ///
+        /// let payload = cell.take_panic_payload().unwrap();
+        /// assert_eq!(payload.downcast_ref::<&str>(), Some(&"kaboom"));
+        /// assert!(cell.is_poisoned());
+        /// ```
+        #[cfg(feature = "std")]
+        pub fn take_panic_payload(&self) -> Option<Box<dyn std::any::Any + Send>> {
+            self.inner.take_panic_payload()
         }
 
         /// Gets the reference to the underlying value. Returns `None`
         /// if the cell is empty, or being initialized. This method does
         /// not block.
         pub fn get(&self) -> Option<&T> {
-            self.0.get()
+            self.inner.get()
+        }
+
+        /// Returns the current state of the cell. Unlike `get`, this can
+        /// tell an empty cell from one which is being initialized, and
+        /// names the thread which runs the initializing function.
+        ///
+        /// The state can change concurrently, so by the time this method
+        /// returns, it might be out of date. This method doesn't block.
+        ///
+        /// This method requires the `std` feature.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::{sync::OnceCell, CellState};
+        ///
+        /// static CELL: OnceCell<u32> = OnceCell::new();
+        ///
+        /// fn main() {
+        ///     assert_eq!(CELL.state(), CellState::Uninit);
+        ///     CELL.get_or_init(|| {
+        ///         let thread = std::thread::current().id();
+        ///         assert_eq!(CELL.state(), CellState::Initializing { thread });
+        ///         92
+        ///     });
+        ///     assert_eq!(CELL.state(), CellState::Ready);
+        /// }
+        /// ```
+        #[cfg(feature = "std")]
+        pub fn state(&self) -> CellState {
+            if self.get().is_some() {
+                CellState::Ready
+            } else if self.is_poisoned() {
+                CellState::Poisoned
+            } else if let Some(thread) = self.inner.initializing_thread() {
+                CellState::Initializing { thread }
+            } else {
+                CellState::Uninit
+            }
+        }
+
+        /// Gets the reference to the underlying value, blocking the current
+        /// thread until it is set. Returns immediately if the cell is
+        /// already initialized.
+        ///
+        /// This is useful when the cell is used as a one-shot rendezvous:
+        /// one thread calls `set` or `get_or_init`, and others `wait` for
+        /// the value.
+        ///
+        /// # Panics
+        ///
+        /// Panics if called from the initializing function of this cell,
+        /// which would deadlock otherwise. The exception is the `spin`
+        /// implementation without `std`, which spins forever instead.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::sync::OnceCell;
+        ///
+        /// static CELL: OnceCell<String> = OnceCell::new();
+        ///
+        /// fn main() {
+        ///     let consumer = std::thread::spawn(|| CELL.wait().len());
+        ///     CELL.set("Hello, World!".to_string()).unwrap();
+        ///     assert_eq!(consumer.join().unwrap(), 13);
+        /// }
+        /// ```
+        pub fn wait(&self) -> &T {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            self.inner.wait(Self::NAME)
+        }
+
+        /// Gets the reference to the underlying value, blocking the current
+        /// thread for at most `timeout` until it is set. Returns `None` if
+        /// the cell is still empty after the timeout.
+        ///
+        /// This method requires the `std` feature.
+        ///
+        /// # Panics
+        ///
+        /// Panics if called from the initializing function of this cell.
+        ///
+        /// # Example
+        /// ```
+        /// use std::time::Duration;
+        ///
+        /// use once_cell::sync::OnceCell;
+        ///
+        /// let cell: OnceCell<i32> = OnceCell::new();
+        /// assert_eq!(cell.wait_timeout(Duration::from_millis(10)), None);
+        /// cell.set(92).unwrap();
+        /// assert_eq!(cell.wait_timeout(Duration::from_millis(10)), Some(&92));
+        /// ```
+        #[cfg(feature = "std")]
+        pub fn wait_timeout(&self, timeout: std::time::Duration) -> Option<&T> {
+            if let Some(value) = self.get() {
+                return Some(value);
+            }
+            self.inner.wait_timeout(Self::NAME, timeout)
+        }
+
+        /// Gets the mutable reference to the underlying value.
+        /// Returns `None` if the cell is empty.
+        ///
+        /// This method never blocks: unique access to the cell
+        /// guarantees that no other thread is initializing it.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::sync::OnceCell;
+        ///
+        /// let mut cell: OnceCell<u32> = OnceCell::new();
+        /// assert_eq!(cell.get_mut(), None);
+        /// cell.set(92).unwrap();
+        /// *cell.get_mut().unwrap() += 1;
+        /// assert_eq!(cell.get(), Some(&93));
+        /// ```
+        pub fn get_mut(&mut self) -> Option<&mut T> {
+            self.inner.get_mut()
         }
 
         /// Sets the contents of this cell to `value`. Returns
         /// `Ok(())` if the cell was empty and `Err(value)` if it was
         /// full.
         ///
+        /// # Panics
+        ///
+        /// Panics if the cell is poisoned.
+        ///
         /// # Example
         /// ```
         /// use once_cell::sync::OnceCell;
@@ -548,7 +1638,11 @@ pub mod sync {
         /// }
         /// ```
         pub fn set(&self, value: T) -> Result<(), T> {
-            self.0.set(value)
+            if self.get().is_some() {
+                return Err(value);
+            }
+            let _guard = self.lock(Self::NAME);
+            self.inner.set(Self::NAME, value)
         }
 
         /// Gets the contents of the cell, initializing it with `f`
@@ -559,11 +1653,13 @@ pub mod sync {
         /// # Panics
         ///
         /// If `f` panics, the panic is propagated to the caller, and
-        /// the cell remains uninitialized.
+        /// the cell remains uninitialized, or becomes poisoned, depending
+        /// on its `PoisonPolicy`. Panics if the cell is poisoned.
         ///
         /// It is an error to reentrantly initialize the cell from `f`.
-        /// The exact outcome is unspecified. Current implementation
-        /// deadlocks, but this may be changed to a panic in the future.
+        /// Doing so results in a panic, which names the type of the cell.
+        /// The exception is the `spin` implementation without `std`, which
+        /// can't identify threads and spins forever instead.
         ///
         /// # Example
         /// ```
@@ -576,23 +1672,33 @@ pub mod sync {
         /// assert_eq!(value, &92);
         /// ```
         pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
-            self.0.get_or_init(f)
+            self.get_or_init_as(Self::NAME, f)
+        }
+
+        /// `get_or_init` for a wrapper of the cell, which is named `name` if
+        /// the initializer accesses it re-entrantly.
+        pub(crate) fn get_or_init_as<F: FnOnce() -> T>(&self, name: CellName, f: F) -> &T {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            let _guard = self.lock(name);
+            self.inner.get_or_init(name, f)
         }
 
         /// Gets the contents of the cell, initializing it with `f` if
         /// the cell was empty. If the cell was empty and `f` failed, an
         /// error is returned.
         ///
-        /// Note that this method requires `parking_lot` Cargo feature.
-        ///
         /// # Panics
         ///
         /// If `f` panics, the panic is propagated to the caller, and
-        /// the cell remains uninitialized.
+        /// the cell remains uninitialized, or becomes poisoned, depending
+        /// on its `PoisonPolicy`. Panics if the cell is poisoned.
         ///
         /// It is an error to reentrantly initialize the cell from `f`.
-        /// The exact outcome is unspecified. Current implementation
-        /// deadlocks, but this may be changed to a panic in the future.
+        /// Doing so results in a panic, which names the type of the cell.
+        /// The exception is the `spin` implementation without `std`, which
+        /// can't identify threads and spins forever instead.
         ///
         /// # Example
         /// ```
@@ -607,9 +1713,179 @@ pub mod sync {
         /// assert_eq!(value, Ok(&92));
         /// assert_eq!(cell.get(), Some(&92))
         /// ```
-        #[cfg(feature = "parking_lot")]
         pub fn get_or_try_init<F: FnOnce() -> Result<T, E>, E>(&self, f: F) -> Result<&T, E> {
-            self.0.get_or_try_init(f)
+            self.get_or_try_init_as(Self::NAME, f)
+        }
+
+        /// `get_or_try_init` for a wrapper of the cell, like `get_or_init_as`.
+        pub(crate) fn get_or_try_init_as<F: FnOnce() -> Result<T, E>, E>(
+            &self,
+            name: CellName,
+            f: F,
+        ) -> Result<&T, E> {
+            if let Some(value) = self.get() {
+                return Ok(value);
+            }
+            let _guard = self.lock(name);
+            self.inner.get_or_try_init(name, f)
+        }
+
+        /// Like `get_or_try_init`, but if another thread is running the
+        /// initializer, waits for it for at most `timeout`. Returns
+        /// `WaitError::Timeout` if the cell is still being initialized after
+        /// that. The initializer of the other thread keeps running.
+        ///
+        /// The timeout only bounds the time spent waiting for other threads:
+        /// if the cell is empty, and nobody is initializing it, `f` is run
+        /// regardless. With a `lock_api::RawMutex`, the mutex is waited for
+        /// the same way, by polling it.
+        ///
+        /// This method requires the `std` feature.
+        ///
+        /// # Panics
+        ///
+        /// Same as `get_or_try_init`.
+        ///
+        /// # Example
+        /// ```
+        /// use std::{sync::Barrier, thread, time::Duration};
+        ///
+        /// use once_cell::{sync::OnceCell, WaitError};
+        ///
+        /// let cell = OnceCell::new();
+        /// let started = Barrier::new(2);
+        /// thread::scope(|scope| {
+        ///     scope.spawn(|| {
+        ///         cell.get_or_init(|| {
+        ///             started.wait();
+        ///             thread::sleep(Duration::from_millis(100));
+        ///             92
+        ///         })
+        ///     });
+        ///     started.wait();
+        ///     let res = cell.get_or_try_init_timeout(Duration::from_millis(10), || Ok::<_, ()>(62));
+        ///     assert_eq!(res, Err(WaitError::Timeout));
+        /// });
+        /// assert_eq!(cell.get(), Some(&92));
+        /// ```
+        #[cfg(feature = "std")]
+        pub fn get_or_try_init_timeout<F: FnOnce() -> Result<T, E>, E>(
+            &self,
+            timeout: std::time::Duration,
+            f: F,
+        ) -> Result<&T, WaitError<E>> {
+            // If the deadline is not representable, it might as well be never.
+            let deadline = std::time::Instant::now().checked_add(timeout);
+            self.get_or_try_init_until(deadline, None, f)
+        }
+
+        /// Like `get_or_try_init`, but if another thread is running the
+        /// initializer, waits for it only until `cancel` is set. Returns
+        /// `WaitError::Cancelled` if the cell is still being initialized at
+        /// that point. The initializer of the other thread keeps running.
+        ///
+        /// While waiting, the flag is checked every few milliseconds. Like the
+        /// timeout of `get_or_try_init_timeout`, it only cancels waiting for
+        /// other threads: if the cell is empty, and nobody is initializing it,
+        /// `f` is run regardless.
+        ///
+        /// This method requires the `std` feature.
+        ///
+        /// # Panics
+        ///
+        /// Same as `get_or_try_init`.
+        ///
+        /// # Example
+        /// ```
+        /// use std::sync::atomic::AtomicBool;
+        ///
+        /// use once_cell::sync::OnceCell;
+        ///
+        /// let cell = OnceCell::new();
+        /// let cancel = AtomicBool::new(true);
+        /// // Nobody else is initializing the cell, so there's nothing to wait for.
+        /// assert_eq!(cell.get_or_try_init_cancellable(&cancel, || Ok::<_, ()>(92)), Ok(&92));
+        /// ```
+        #[cfg(feature = "std")]
+        pub fn get_or_try_init_cancellable<F: FnOnce() -> Result<T, E>, E>(
+            &self,
+            cancel: &std::sync::atomic::AtomicBool,
+            f: F,
+        ) -> Result<&T, WaitError<E>> {
+            self.get_or_try_init_until(None, Some(cancel), f)
+        }
+
+        #[cfg(feature = "std")]
+        fn get_or_try_init_until<F: FnOnce() -> Result<T, E>, E>(
+            &self,
+            deadline: Option<std::time::Instant>,
+            cancel: Option<&std::sync::atomic::AtomicBool>,
+            f: F,
+        ) -> Result<&T, WaitError<E>> {
+            if let Some(value) = self.get() {
+                return Ok(value);
+            }
+            if deadline.is_none() && cancel.is_none() {
+                return self.get_or_try_init(f).map_err(WaitError::Init);
+            }
+            let give_up = crate::GiveUp { deadline, cancel };
+            let res = self
+                .lock_until(Self::NAME, give_up)
+                .and_then(|_guard| self.inner.get_or_try_init_until(Self::NAME, f, give_up));
+            match res {
+                Some(res) => res.map_err(WaitError::Init),
+                None if give_up.cancelled() => Err(WaitError::Cancelled),
+                None => Err(WaitError::Timeout),
+            }
+        }
+
+        /// Gets the mutable reference to the contents of the cell,
+        /// initializing it with `f` if the cell was empty.
+        ///
+        /// Unlike `get_or_init`, this method doesn't need to synchronize
+        /// with other threads, as it requires unique access to the cell.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::sync::OnceCell;
+        ///
+        /// let mut cell = OnceCell::new();
+        /// let value = cell.get_or_init_mut(|| vec![1, 2]);
+        /// value.push(3);
+        /// assert_eq!(cell.get_or_init_mut(|| unreachable!()), &vec![1, 2, 3]);
+        /// ```
+        pub fn get_or_init_mut<F: FnOnce() -> T>(&mut self, f: F) -> &mut T {
+            if self.get_mut().is_none() {
+                self.get_or_init(f);
+            }
+            match self.get_mut() {
+                Some(value) => value,
+                None => unreachable!(),
+            }
+        }
+
+        /// Takes the value out of this `OnceCell`, moving it back to an uninitialized state.
+        ///
+        /// Has no effect and returns `None` if the `OnceCell` hasn't been initialized.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use once_cell::sync::OnceCell;
+        ///
+        /// let mut cell: OnceCell<String> = OnceCell::new();
+        /// assert_eq!(cell.take(), None);
+        ///
+        /// let mut cell = OnceCell::new();
+        /// cell.set("hello".to_string()).unwrap();
+        /// assert_eq!(cell.take(), Some("hello".to_string()));
+        /// assert_eq!(cell.get(), None);
+        ///
+        /// cell.set("world".to_string()).unwrap();
+        /// assert_eq!(cell.get().map(String::as_str), Some("world"));
+        /// ```
+        pub fn take(&mut self) -> Option<T> {
+            self.inner.take()
         }
 
         /// Consumes the `OnceCell`, returning the wrapped value. Returns
@@ -628,7 +1904,60 @@ pub mod sync {
         /// assert_eq!(cell.into_inner(), Some("hello".to_string()));
         /// ```
         pub fn into_inner(self) -> Option<T> {
-            self.0.into_inner()
+            self.inner.into_inner()
+        }
+
+        /// Names the cell in the panic of a re-entrant initialization.
+        const NAME: CellName = CellName(|f| {
+            if R::BUILTIN {
+                write!(f, "sync::OnceCell<{}>", type_name::<T>())
+            } else {
+                write!(f, "sync::OnceCellWith<{}, {}>", type_name::<T>(), type_name::<R>())
+            }
+        });
+
+        /// Locks `R` for a thread which is about to initialize the cell.
+        /// With `Builtin`, this does nothing.
+        fn lock(&self, name: CellName) -> LockGuard<'_, R> {
+            if !self.lock.try_lock() {
+                self.check_reentrancy(name);
+                self.lock.lock();
+            }
+            LockGuard { lock: &self.lock }
+        }
+
+        /// Like `lock`, but returns `None` if it's time to `give_up` before
+        /// the lock is free.
+        #[cfg(feature = "std")]
+        fn lock_until(
+            &self,
+            name: CellName,
+            give_up: crate::GiveUp<'_>,
+        ) -> Option<LockGuard<'_, R>> {
+            // `lock_api::RawMutex` can't block with a timeout, so we poll.
+            const LOCK_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(1);
+
+            if !self.lock.try_lock() {
+                self.check_reentrancy(name);
+                while !self.lock.try_lock() {
+                    if give_up.due() {
+                        return None;
+                    }
+                    std::thread::sleep(LOCK_POLL_INTERVAL);
+                }
+            }
+            Some(LockGuard { lock: &self.lock })
+        }
+
+        /// Called when the lock is held by an initializer. If it runs on the
+        /// current thread, blocking on the lock would deadlock.
+        fn check_reentrancy(&self, name: CellName) {
+            #[cfg(feature = "std")]
+            if self.inner.initializing_thread() == Some(std::thread::current().id()) {
+                crate::owner::reentrant(name)
+            }
+            #[cfg(not(feature = "std"))]
+            let _ = name;
         }
     }
 
@@ -665,21 +1994,167 @@ pub mod sync {
     ///     //   Some("Hoyten")
     /// }
     /// ```
-    #[derive(Debug)]
-    pub struct Lazy<T, F = fn() -> T> {
-        cell: OnceCell<T>,
-        init: F,
+    ///
+    /// The initializing function is called at most once, so it can move
+    /// captured values into the result. It is dropped right after the call.
+    ///
+    /// # Panics
+    ///
+    /// If the initializing function panics, the panic is propagated to the
+    /// caller, and the `Lazy` becomes poisoned: the function can't be called
+    /// again, so all subsequent accesses, from any thread, panic as well.
+    pub type Lazy<T, F = fn() -> T> = LazyWith<T, Builtin, F>;
+
+    /// A value which is initialized on the first access, like `Lazy`, and
+    /// which holds the lock `R` while it runs the initializing function.
+    /// This is `Lazy` built on `OnceCellWith`.
+    ///
+    /// # Example
+    /// ```
+    /// # #[cfg(feature = "lock_api")] {
+    /// use std::collections::HashMap;
+    ///
+    /// use once_cell::sync::LazyWith;
+    ///
+    /// static HASHMAP: LazyWith<HashMap<i32, String>, parking_lot::RawMutex> = LazyWith::new(|| {
+    ///     let mut m = HashMap::new();
+    ///     m.insert(13, "Spica".to_string());
+    ///     m.insert(74, "Hoyten".to_string());
+    ///     m
+    /// });
+    ///
+    /// assert_eq!(HASHMAP.get(&13).map(String::as_str), Some("Spica"));
+    /// # }
+    /// ```
+    pub struct LazyWith<T, R, F = fn() -> T> {
+        cell: OnceCellWith<T, R>,
+        init: Cell<Option<F>>,
     }
 
-    impl<T, F> Lazy<T, F> {
+    impl<T: fmt::Debug, R: Lock, F> fmt::Debug for LazyWith<T, R, F> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = if R::BUILTIN { "Lazy" } else { "LazyWith" };
+            f.debug_struct(name).field("cell", &self.cell).field("init", &"..").finish()
+        }
+    }
+
+    impl<T: Default, R: Lock> Default for LazyWith<T, R> {
+        /// Creates a new lazy value, which is initialized with `T::default()`.
+        fn default() -> LazyWith<T, R> {
+            LazyWith::new(T::default)
+        }
+    }
+
+    // We never create a `&F` from a `&LazyWith<T, R, F>` so it is fine
+    // to not impl `Sync` for `F`. We do create a `&mut Option<F>` in
+    // `force`, but `OnceCellWith` guarantees that it is done at most once, by
+    // the single thread which runs the initializer.
+    unsafe impl<T, R, F: Send> Sync for LazyWith<T, R, F> where OnceCellWith<T, R>: Sync {}
+    // auto-derived `Send` impl is OK.
+
+    impl<T, R, F: RefUnwindSafe> RefUnwindSafe for LazyWith<T, R, F> where
+        OnceCellWith<T, R>: RefUnwindSafe
+    {
+    }
+
+    impl<T, R: Lock, F> LazyWith<T, R, F> {
         /// Creates a new lazy value with the given initializing
         /// function.
-        pub const fn new(f: F) -> Lazy<T, F> {
-            Lazy { cell: OnceCell::new(), init: f }
+        pub const fn new(f: F) -> LazyWith<T, R, F> {
+            // The function is consumed by the first attempt to initialize the
+            // value, so there's nothing to retry with.
+            LazyWith {
+                cell: OnceCellWith::with_poison_policy(PoisonPolicy::Poison),
+                init: Cell::new(Some(f)),
+            }
+        }
+
+        /// Returns `true` if the initializing function has panicked. All
+        /// accesses to a poisoned `Lazy`, from any thread, panic.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::sync::Lazy;
+        ///
+        /// let lazy: Lazy<i32> = Lazy::new(|| panic!("kaboom"));
+        /// assert!(!Lazy::is_poisoned(&lazy));
+        /// assert!(std::panic::catch_unwind(|| *lazy).is_err());
+        /// assert!(Lazy::is_poisoned(&lazy));
+        /// ```
+        pub fn is_poisoned(this: &LazyWith<T, R, F>) -> bool {
+            this.cell.is_poisoned()
+        }
+
+        /// Gets the reference to the result of this lazy value if
+        /// it was initialized, otherwise returns `None`. Unlike `force`,
+        /// this never runs the initializing function.
+        ///
+        /// This is an associated function rather than a method, so that it
+        /// doesn't shadow methods of `T` through `Deref`.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::sync::Lazy;
+        ///
+        /// let lazy = Lazy::new(|| 92);
+        ///
+        /// assert_eq!(Lazy::get(&lazy), None);
+        /// assert_eq!(&*lazy, &92);
+        /// assert_eq!(Lazy::get(&lazy), Some(&92));
+        /// ```
+        pub fn get(this: &LazyWith<T, R, F>) -> Option<&T> {
+            this.cell.get()
+        }
+
+        /// Gets the mutable reference to the result of this lazy value if
+        /// it was initialized, otherwise returns `None`. This never runs the
+        /// initializing function.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::sync::Lazy;
+        ///
+        /// let mut lazy = Lazy::new(|| 92);
+        ///
+        /// assert_eq!(Lazy::get_mut(&mut lazy), None);
+        /// assert_eq!(*lazy, 92);
+        /// *Lazy::get_mut(&mut lazy).unwrap() += 1;
+        /// assert_eq!(*lazy, 93);
+        /// ```
+        pub fn get_mut(this: &mut LazyWith<T, R, F>) -> Option<&mut T> {
+            this.cell.get_mut()
+        }
+
+        /// Consumes this lazy value, returning the result if it was
+        /// initialized, or the initializing function if it never ran.
+        ///
+        /// # Panics
+        ///
+        /// Panics if the `Lazy` is poisoned, that is, if the initializing
+        /// function was called, but panicked.
+        ///
+        /// # Example
+        /// ```
+        /// use once_cell::sync::Lazy;
+        ///
+        /// let hello = "Hello, World!".to_string();
+        /// let lazy: Lazy<String, _> = Lazy::new(|| hello.to_uppercase());
+        /// let init = Lazy::into_value(lazy).unwrap_err();
+        /// assert_eq!(init(), "HELLO, WORLD!");
+        ///
+        /// let lazy = Lazy::new(|| 92);
+        /// assert_eq!(*lazy, 92);
+        /// assert_eq!(Lazy::into_value(lazy).ok(), Some(92));
+        /// ```
+        pub fn into_value(this: LazyWith<T, R, F>) -> Result<T, F> {
+            let LazyWith { cell, init } = this;
+            cell.into_inner().ok_or_else(|| {
+                init.take().unwrap_or_else(|| panic!("Lazy instance has previously been poisoned"))
+            })
         }
     }
 
-    impl<T, F: Fn() -> T> Lazy<T, F> {
+    impl<T, R: Lock, F: FnOnce() -> T> LazyWith<T, R, F> {
         /// Forces the evaluation of this lazy value and
         /// returns a reference to result. This is equivalent
         /// to the `Deref` impl, but is explicit.
@@ -693,15 +2168,166 @@ pub mod sync {
         /// assert_eq!(Lazy::force(&lazy), &92);
         /// assert_eq!(&*lazy, &92);
         /// ```
-        pub fn force(this: &Lazy<T, F>) -> &T {
-            this.cell.get_or_init(|| (this.init)())
+        pub fn force(this: &LazyWith<T, R, F>) -> &T {
+            if let Some(value) = this.cell.get() {
+                return value;
+            }
+            if this.cell.is_poisoned() {
+                panic!("Lazy instance has previously been poisoned")
+            }
+            this.cell.get_or_init_as(Self::NAME, || match this.init.take() {
+                Some(f) => f(),
+                None => panic!("Lazy instance has previously been poisoned"),
+            })
         }
+
+        /// Names the value in the panic of a re-entrant initialization.
+        const NAME: CellName = CellName(|f| {
+            if R::BUILTIN {
+                write!(f, "sync::Lazy<{}>", type_name::<T>())
+            } else {
+                write!(f, "sync::LazyWith<{}, {}>", type_name::<T>(), type_name::<R>())
+            }
+        });
     }
 
-    impl<T, F: Fn() -> T> ::std::ops::Deref for Lazy<T, F> {
+    impl<T, R: Lock, F: FnOnce() -> T> Deref for LazyWith<T, R, F> {
         type Target = T;
         fn deref(&self) -> &T {
-            Lazy::force(self)
+            LazyWith::force(self)
+        }
+    }
+
+    /// A value which is initialized on the first access, by a function which
+    /// can fail. Thread-safe version of `unsync::TryLazy`.
+    ///
+    /// This is a `Lazy` for values like configuration or connection pools,
+    /// where the initializing function returns a `Result`: `force` returns
+    /// the error to the caller instead of panicking.
+    ///
+    /// With the default `Retry` policy, a failed initialization leaves the
+    /// `TryLazy` empty, and the next access calls the function again. With
+    /// the `CacheError` policy, the first error is stored, and all subsequent
+    /// accesses, from any thread, return a clone of it without calling the
+    /// function. Because the function may be called more than once, it must
+    /// be `Fn`.
+    ///
+    /// Like with `OnceCell::get_or_try_init`, only one thread at a time runs
+    /// the initializing function, while others block.
+    ///
+    /// # Example
+    /// ```
+    /// use std::{env, num::ParseIntError};
+    ///
+    /// use once_cell::sync::TryLazy;
+    ///
+    /// static PORT: TryLazy<u16, ParseIntError> = TryLazy::new(|| {
+    ///     env::var("APP_PORT").unwrap_or_default().parse()
+    /// });
+    ///
+    /// fn main() {
+    ///     env::set_var("APP_PORT", "eighty");
+    ///     assert!(PORT.force().is_err());
+    ///
+    ///     // The error is not cached, so the next access tries again.
+    ///     env::set_var("APP_PORT", "80");
+    ///     assert_eq!(PORT.force(), Ok(&80));
+    /// }
+    /// ```
+    pub struct TryLazy<T, E, F = fn() -> Result<T, E>, P: TryLazyPolicy<OnceCell<E>> = Retry> {
+        cell: OnceCell<T>,
+        error: P::Errors,
+        init: F,
+        policy: PhantomData<P>,
+    }
+
+    impl<E> TryLazyPolicy<OnceCell<E>> for CacheError {
+        type Errors = OnceCell<E>;
+        #[allow(clippy::declare_interior_mutable_const)]
+        const NO_ERRORS: OnceCell<E> = OnceCell::new();
+    }
+
+    impl<T, E, F, P> fmt::Debug for TryLazy<T, E, F, P>
+    where
+        T: fmt::Debug,
+        P: TryLazyPolicy<OnceCell<E>>,
+        P::Errors: fmt::Debug,
+    {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("TryLazy")
+                .field("cell", &self.cell)
+                .field("error", &self.error)
+                .field("init", &"..")
+                .finish()
+        }
+    }
+
+    impl<T, E, F, P: TryLazyPolicy<OnceCell<E>>> TryLazy<T, E, F, P> {
+        /// Creates a new lazy value with the given initializing function.
+        pub const fn new(init: F) -> TryLazy<T, E, F, P> {
+            TryLazy { cell: OnceCell::new(), error: P::NO_ERRORS, init, policy: PhantomData }
+        }
+
+        /// Gets the reference to the result of this lazy value if it was
+        /// successfully initialized, without calling the initializing
+        /// function.
+        pub fn get(&self) -> Option<&T> {
+            self.cell.get()
+        }
+
+        /// Names the value in the panic of a re-entrant initialization.
+        const NAME: CellName =
+            CellName(|f| write!(f, "sync::TryLazy<{}, {}>", type_name::<T>(), type_name::<E>()));
+    }
+
+    impl<T, E, F> TryLazy<T, E, F, Retry> {
+        /// Always returns `None`: under the `Retry` policy, errors are not
+        /// cached.
+        pub fn error(&self) -> Option<&E> {
+            None
+        }
+    }
+
+    impl<T, E, F> TryLazy<T, E, F, CacheError> {
+        /// Gets the reference to the cached error, if the initializing
+        /// function has failed.
+        pub fn error(&self) -> Option<&E> {
+            self.error.get()
+        }
+    }
+
+    impl<T, E, F: Fn() -> Result<T, E>> TryLazy<T, E, F, Retry> {
+        /// Forces the evaluation of this lazy value and returns a reference
+        /// to the result, or the error returned by the initializing function.
+        ///
+        /// If the value was not initialized yet, calls the initializing
+        /// function, even if it has failed before.
+        pub fn force(&self) -> Result<&T, E> {
+            self.cell.get_or_try_init_as(Self::NAME, &self.init)
+        }
+    }
+
+    impl<T, E: Clone, F: Fn() -> Result<T, E>> TryLazy<T, E, F, CacheError> {
+        /// Forces the evaluation of this lazy value and returns a reference
+        /// to the result, or the error returned by the initializing function.
+        ///
+        /// If the initializing function has failed before, returns a clone of
+        /// that error without calling the function again.
+        pub fn force(&self) -> Result<&T, E> {
+            if let Some(err) = self.error.get() {
+                return Err(err.clone());
+            }
+            self.cell.get_or_try_init_as(Self::NAME, || {
+                // Another thread might have failed while we were waiting.
+                if let Some(err) = self.error.get() {
+                    return Err(err.clone());
+                }
+                let res = (self.init)();
+                if let Err(err) = &res {
+                    let _ = self.error.set(err.clone());
+                }
+                res
+            })
         }
     }
 }
@@ -0,0 +1,90 @@
+//! The locks which serialize the initializers of a `sync::OnceCellWith`.
+//!
+//! `Builtin` leaves everything to the implementation of `sync::OnceCell`. With
+//! the `lock_api` feature, any `lock_api::RawMutex` can be used instead: the
+//! initializing thread holds the mutex while it runs the initializer, and the
+//! other threads which want to initialize the cell block on the mutex. So the
+//! cell inherits the properties of the mutex, like priority inheritance or
+//! instrumentation, which the built-in implementations can't provide.
+
+#[cfg(feature = "lock_api")]
+use lock_api::RawMutex;
+
+/// A lock which a `sync::OnceCellWith` holds while it runs the initializer.
+///
+/// This trait is implemented by `Builtin` and, with the `lock_api` feature,
+/// by every `lock_api::RawMutex`. It can't be implemented outside of this
+/// crate.
+pub trait Lock: sealed::Sealed {
+    #[doc(hidden)]
+    const INIT: Self;
+    /// Whether this is `Builtin`, to name the cell in `Debug`.
+    #[doc(hidden)]
+    const BUILTIN: bool;
+    #[doc(hidden)]
+    fn try_lock(&self) -> bool;
+    #[doc(hidden)]
+    fn lock(&self);
+    #[doc(hidden)]
+    fn unlock(&self);
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// The lock of `sync::OnceCell`, which is `sync::OnceCellWith<T, Builtin>`.
+///
+/// It takes no room, and never blocks: the threads which race to initialize
+/// the cell block like those which `wait` for it, in the way the
+/// implementation of `sync` selected by the Cargo features provides.
+#[derive(Debug)]
+pub struct Builtin(());
+
+impl sealed::Sealed for Builtin {}
+
+impl Lock for Builtin {
+    const INIT: Builtin = Builtin(());
+    const BUILTIN: bool = true;
+
+    fn try_lock(&self) -> bool {
+        true
+    }
+
+    fn lock(&self) {}
+
+    fn unlock(&self) {}
+}
+
+#[cfg(feature = "lock_api")]
+impl<R: RawMutex> sealed::Sealed for R {}
+
+#[cfg(feature = "lock_api")]
+impl<R: RawMutex> Lock for R {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: R = <R as RawMutex>::INIT;
+    const BUILTIN: bool = false;
+
+    fn try_lock(&self) -> bool {
+        RawMutex::try_lock(self)
+    }
+
+    fn lock(&self) {
+        RawMutex::lock(self)
+    }
+
+    fn unlock(&self) {
+        RawMutex::unlock(self)
+    }
+}
+
+/// Unlocks the lock when dropped, also on panic.
+pub(crate) struct Guard<'a, R: Lock> {
+    pub(crate) lock: &'a R,
+}
+
+impl<R: Lock> Drop for Guard<'_, R> {
+    fn drop(&mut self) {
+        self.lock.unlock();
+    }
+}
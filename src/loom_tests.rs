@@ -0,0 +1,122 @@
+//! Model tests for the implementations of `sync::OnceCell`, which check every
+//! interleaving of the threads with `loom`. Run them with
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --lib loom
+//! ```
+//!
+//! adding `--no-default-features --features std`, `spin`, or `futex`, to
+//! check the other implementations.
+
+use loom::{
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc,
+    },
+    thread,
+};
+
+use crate::{imp::OnceCell, policy::PoisonPolicy, CellName};
+
+const NAME: CellName = CellName(|f| f.write_str("OnceCell"));
+
+#[test]
+fn get_or_init_runs_once() {
+    loom::model(|| {
+        let cell = Arc::new(OnceCell::new(PoisonPolicy::Retry));
+        let n_calls = Arc::new(AtomicUsize::new(0));
+        let thread = {
+            let (cell, n_calls) = (Arc::clone(&cell), Arc::clone(&n_calls));
+            thread::spawn(move || {
+                let value = cell.get_or_init(NAME, || {
+                    n_calls.fetch_add(1, SeqCst);
+                    "spam".to_string()
+                });
+                value.clone()
+            })
+        };
+        let value = cell.get_or_init(NAME, || {
+            n_calls.fetch_add(1, SeqCst);
+            "eggs".to_string()
+        });
+        assert_eq!(value, &thread.join().unwrap());
+        assert_eq!(n_calls.load(SeqCst), 1);
+    });
+}
+
+#[test]
+fn set_get() {
+    loom::model(|| {
+        let cell = Arc::new(OnceCell::new(PoisonPolicy::Retry));
+        let thread = {
+            let cell = Arc::clone(&cell);
+            thread::spawn(move || cell.set(NAME, "hello".to_string()).unwrap())
+        };
+        // The value is either not there yet, or fully written.
+        if let Some(value) = cell.get() {
+            assert_eq!(value, "hello");
+        }
+        thread.join().unwrap();
+        assert_eq!(cell.get().map(String::as_str), Some("hello"));
+    });
+}
+
+#[test]
+fn set_set() {
+    loom::model(|| {
+        let cell = Arc::new(OnceCell::new(PoisonPolicy::Retry));
+        let thread = {
+            let cell = Arc::clone(&cell);
+            thread::spawn(move || cell.set(NAME, 1).is_ok())
+        };
+        let ours = cell.set(NAME, 2).is_ok();
+        let theirs = thread.join().unwrap();
+        assert!(ours != theirs);
+        assert_eq!(cell.get(), Some(if ours { &2 } else { &1 }));
+    });
+}
+
+#[test]
+fn get_or_try_init_failure_lets_others_retry() {
+    loom::model(|| {
+        let cell = Arc::new(OnceCell::new(PoisonPolicy::Retry));
+        let thread = {
+            let cell = Arc::clone(&cell);
+            thread::spawn(move || cell.get_or_try_init(NAME, || Err(())).copied())
+        };
+        assert_eq!(cell.get_or_init(NAME, || 92), &92);
+        // The other thread either failed, or observed our value.
+        if let Ok(value) = thread.join().unwrap() {
+            assert_eq!(value, 92);
+        }
+    });
+}
+
+#[test]
+fn wait_set() {
+    loom::model(|| {
+        let cell = Arc::new(OnceCell::new(PoisonPolicy::Retry));
+        let thread = {
+            let cell = Arc::clone(&cell);
+            thread::spawn(move || *cell.wait(NAME))
+        };
+        cell.set(NAME, 92).unwrap();
+        assert_eq!(thread.join().unwrap(), 92);
+    });
+}
+
+#[test]
+fn get_or_try_init_failure_wakes_waiters() {
+    // Nobody ever sets the cell. If a thread waits for the other's
+    // initializer, it must be woken up when the initializer fails, even if
+    // it fails before the thread goes to sleep.
+    loom::model(|| {
+        let cell = Arc::new(OnceCell::<i32>::new(PoisonPolicy::Retry));
+        let thread = {
+            let cell = Arc::clone(&cell);
+            thread::spawn(move || cell.get_or_try_init(NAME, || Err(())).copied())
+        };
+        assert_eq!(cell.get_or_try_init(NAME, || Err(())), Err(()));
+        assert_eq!(thread.join().unwrap(), Err(()));
+    });
+}
@@ -0,0 +1,172 @@
+use std::{
+    any::type_name,
+    borrow::Borrow,
+    collections::{hash_map::RandomState, HashMap},
+    fmt,
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::{sync::OnceCell, CellName};
+
+/// A concurrent map, in which every value is initialized at most once.
+///
+/// Values are initialized lazily by `get_or_init`, and the returned
+/// references live as long as the map itself. For each key, only one
+/// initializing function runs, and other threads which ask for the same key
+/// block until it completes. Different keys are initialized concurrently: the
+/// map is locked only to find the slot for a key, not while the initializing
+/// function runs.
+///
+/// Each value is stored in its own heap-allocated `OnceCell`, which doesn't
+/// move when the map grows. Values are removed only through `&mut self`.
+///
+/// Note that this type requires the `std` feature.
+///
+/// # Example
+/// ```
+/// use once_cell::sync::{Lazy, OnceMap};
+///
+/// static LENGTHS: Lazy<OnceMap<&'static str, usize>> = Lazy::new(OnceMap::new);
+///
+/// fn length(s: &'static str) -> &'static usize {
+///     LENGTHS.get_or_init(s, || s.len())
+/// }
+///
+/// fn main() {
+///     let hello = length("hello");
+///     assert_eq!(length("world!"), &6);
+///     assert_eq!(hello, &5);
+///     assert!(std::ptr::eq(hello, length("hello")));
+/// }
+/// ```
+pub struct OnceMap<K, V, S = RandomState> {
+    map: Mutex<HashMap<K, Box<OnceCell<V>>, S>>,
+    // `Mutex` is `Sync` if the map is merely `Send`, but we hand out `&V`
+    // to all threads, so we need `V: Sync` as well, like `OnceCell` does.
+    _marker: PhantomData<OnceCell<V>>,
+}
+
+impl<K, V> OnceMap<K, V> {
+    /// Creates a new empty map.
+    pub fn new() -> OnceMap<K, V> {
+        OnceMap::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, V, S> OnceMap<K, V, S> {
+    /// Creates a new empty map, which uses `hash_builder` to hash the keys.
+    pub fn with_hasher(hash_builder: S) -> OnceMap<K, V, S> {
+        OnceMap { map: Mutex::new(HashMap::with_hasher(hash_builder)), _marker: PhantomData }
+    }
+
+    fn map(&self) -> MutexGuard<'_, HashMap<K, Box<OnceCell<V>>, S>> {
+        // Only the user's `Hash` and `Eq` impls can panic while the lock is
+        // held, which doesn't leave the map itself in an inconsistent state.
+        self.map.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn map_mut(&mut self) -> &mut HashMap<K, Box<OnceCell<V>>, S> {
+        self.map.get_mut().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> OnceMap<K, V, S> {
+    /// Gets the reference to the value for `key`. Returns `None` if there's
+    /// no such value, or if it is being initialized. This method doesn't
+    /// wait for the initializing function.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let cell = self.map().get(key).map(|cell| self.extend(cell))?;
+        cell.get()
+    }
+
+    /// Gets the value for `key`, initializing it with `f` if there was no
+    /// value.
+    ///
+    /// If several threads call `get_or_init` with the same key concurrently,
+    /// only one `f` is executed, and the others block until it completes.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics, the panic is propagated to the caller, and the value
+    /// stays uninitialized. It is an error to initialize the same key
+    /// re-entrantly from `f`, which results in a panic.
+    pub fn get_or_init<F>(&self, key: K, f: F) -> &V
+    where
+        F: FnOnce() -> V,
+    {
+        self.slot(key).get_or_init_as(Self::NAME, f)
+    }
+
+    /// Gets the value for `key`, initializing it with `f` if there was no
+    /// value. If there was no value and `f` failed, an error is returned.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics, the panic is propagated to the caller, and the value
+    /// stays uninitialized. It is an error to initialize the same key
+    /// re-entrantly from `f`, which results in a panic.
+    pub fn get_or_try_init<F, E>(&self, key: K, f: F) -> Result<&V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        self.slot(key).get_or_try_init_as(Self::NAME, f)
+    }
+
+    /// Gets the mutable reference to the value for `key`, if it is
+    /// initialized.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map_mut().get_mut(key)?.get_mut()
+    }
+
+    /// Removes the value for `key` from the map, returning it if it was
+    /// initialized.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map_mut().remove(key)?.into_inner()
+    }
+
+    fn slot(&self, key: K) -> &OnceCell<V> {
+        let mut map = self.map();
+        let cell = map.entry(key).or_insert_with(|| Box::new(OnceCell::new()));
+        self.extend(cell)
+    }
+
+    /// Extends the lifetime of a reference to a slot from that of the lock
+    /// guard to that of the map.
+    fn extend<'a>(&'a self, cell: &OnceCell<V>) -> &'a OnceCell<V> {
+        // Safe, because each cell is boxed, so it doesn't move when the map
+        // is resized, and cells are removed from the map only through
+        // `&mut self`, which can't coexist with the returned reference.
+        unsafe { &*(cell as *const OnceCell<V>) }
+    }
+
+    /// Names the map in the panic of a re-entrant initialization.
+    const NAME: CellName =
+        CellName(|f| write!(f, "sync::OnceMap<{}, {}>", type_name::<K>(), type_name::<V>()));
+}
+
+impl<K, V> Default for OnceMap<K, V> {
+    fn default() -> OnceMap<K, V> {
+        OnceMap::new()
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for OnceMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let map = self.map();
+        f.debug_map().entries(map.iter()).finish()
+    }
+}
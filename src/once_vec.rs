@@ -0,0 +1,326 @@
+//! Append-only vectors, which hand out stable references to their elements.
+//!
+//! The elements are stored in segments, which are allocated lazily, when the
+//! first element which belongs to them is pushed. The first segment holds
+//! `FIRST_SEGMENT_LEN` elements, and each next one is twice as large as the
+//! previous one, so a handful of segments covers the whole `usize` range.
+//! Segments are never reallocated, so references to the elements don't move.
+
+#[cfg(once_cell_sync)]
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{boxed::Box, vec::Vec};
+
+const FIRST_SEGMENT_SHIFT: u32 = 3;
+const FIRST_SEGMENT_LEN: usize = 1 << FIRST_SEGMENT_SHIFT;
+const SEGMENTS: usize = (usize::BITS - FIRST_SEGMENT_SHIFT) as usize;
+
+/// Returns the segment and the offset in the segment of the element at `idx`,
+/// or `None` if the index is too large to be ever pushed.
+fn location(idx: usize) -> Option<(usize, usize)> {
+    let i = idx.checked_add(FIRST_SEGMENT_LEN)?;
+    let bit = usize::BITS - 1 - i.leading_zeros();
+    Some(((bit - FIRST_SEGMENT_SHIFT) as usize, i - (1 << bit)))
+}
+
+fn new_segment<S>(segment: usize, slot: impl Fn() -> S) -> Box<[S]> {
+    let len = FIRST_SEGMENT_LEN << segment;
+    (0..len).map(|_| slot()).collect::<Vec<_>>().into_boxed_slice()
+}
+
+fn capacity_overflow() -> ! {
+    panic!("OnceVec capacity overflow")
+}
+
+pub mod unsync {
+    use core::{
+        cell::{Cell, UnsafeCell},
+        fmt,
+        mem::MaybeUninit,
+        ptr,
+    };
+
+    use alloc::boxed::Box;
+
+    use super::{capacity_overflow, location, new_segment, SEGMENTS};
+    use crate::unsync::OnceCell;
+
+    type Segment<T> = Box<[UnsafeCell<MaybeUninit<T>>]>;
+
+    /// An append-only vector, which can be pushed to through a shared
+    /// reference. Not thread safe.
+    ///
+    /// Elements are never moved once pushed, so `push` and `get` return
+    /// references which live as long as the vector itself. This makes
+    /// `OnceVec` a good fit for arenas and interners.
+    ///
+    /// Note that this type requires the `alloc` feature.
+    ///
+    /// # Example
+    /// ```
+    /// use once_cell::unsync::OnceVec;
+    ///
+    /// let vec = OnceVec::new();
+    /// let (idx, hello) = vec.push("hello".to_string());
+    /// vec.push("world".to_string());
+    /// assert_eq!(idx, 0);
+    /// assert_eq!(hello, "hello");
+    /// assert_eq!(vec.get(1).map(String::as_str), Some("world"));
+    /// assert_eq!(vec.len(), 2);
+    /// ```
+    pub struct OnceVec<T> {
+        len: Cell<usize>,
+        segments: [OnceCell<Segment<T>>; SEGMENTS],
+    }
+
+    impl<T> OnceVec<T> {
+        // Only used to initialize the array, which can't be done with a
+        // `Copy` value, as cells aren't `Copy`.
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY_SEGMENT: OnceCell<Segment<T>> = OnceCell::new();
+
+        /// Creates a new empty vector. Doesn't allocate.
+        pub const fn new() -> OnceVec<T> {
+            OnceVec { len: Cell::new(0), segments: [Self::EMPTY_SEGMENT; SEGMENTS] }
+        }
+
+        /// Returns the number of elements in the vector.
+        pub fn len(&self) -> usize {
+            self.len.get()
+        }
+
+        /// Returns `true` if the vector has no elements.
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// Gets the reference to the element at `idx`, or `None` if it is out
+        /// of bounds.
+        pub fn get(&self, idx: usize) -> Option<&T> {
+            if idx >= self.len() {
+                return None;
+            }
+            let (segment, offset) = location(idx)?;
+            let slot = self.segments[segment].get()?[offset].get();
+            // Safe, because every element below `len` is initialized, and
+            // elements are never mutated through `&self`.
+            Some(unsafe { &*(*slot).as_ptr() })
+        }
+
+        /// Appends `value` to the vector, returning its index and a reference
+        /// to it.
+        ///
+        /// # Panics
+        ///
+        /// Panics if the number of elements overflows a `usize`.
+        pub fn push(&self, value: T) -> (usize, &T) {
+            let idx = self.len();
+            let (segment, offset) = match location(idx) {
+                Some(it) => it,
+                None => capacity_overflow(),
+            };
+            let segment = self.segments[segment]
+                .get_or_init(|| new_segment(segment, || UnsafeCell::new(MaybeUninit::uninit())));
+            // Safe, because the slot at `len` is not initialized, so there are
+            // no references to it, and, as the vector is not `Sync`, no one
+            // can push concurrently.
+            let value = unsafe {
+                let slot = &mut *segment[offset].get();
+                ptr::write(slot.as_mut_ptr(), value);
+                &*slot.as_ptr()
+            };
+            self.len.set(idx + 1);
+            (idx, value)
+        }
+    }
+
+    impl<T> Default for OnceVec<T> {
+        fn default() -> OnceVec<T> {
+            OnceVec::new()
+        }
+    }
+
+    impl<T: fmt::Debug> fmt::Debug for OnceVec<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_list().entries((0..self.len()).filter_map(|idx| self.get(idx))).finish()
+        }
+    }
+
+    impl<T> Drop for OnceVec<T> {
+        fn drop(&mut self) {
+            for idx in 0..self.len() {
+                let (segment, offset) = location(idx).unwrap();
+                let segment = self.segments[segment].get_mut().unwrap();
+                // Safe, because every element below `len` is initialized.
+                unsafe { ptr::drop_in_place((*segment[offset].get()).as_mut_ptr()) }
+            }
+        }
+    }
+}
+
+#[cfg(once_cell_sync)]
+pub mod sync {
+    use core::{
+        any::type_name,
+        fmt,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use alloc::boxed::Box;
+
+    use super::{capacity_overflow, location, new_segment, Slot, SEGMENTS};
+    use crate::{sync::OnceCell, CellName};
+
+    /// A thread-safe append-only vector, which can be pushed to through a
+    /// shared reference.
+    ///
+    /// Elements are never moved once pushed, so `push` and `get` return
+    /// references which live as long as the vector itself. Reads never lock:
+    /// `get` only loads a couple of atomics. Concurrent `push`es write to
+    /// different slots, and block each other only while a new segment is
+    /// allocated.
+    ///
+    /// Note that this type requires the `alloc` feature.
+    ///
+    /// # Example
+    /// ```
+    /// use once_cell::sync::OnceVec;
+    ///
+    /// static NAMES: OnceVec<String> = OnceVec::new();
+    ///
+    /// fn main() {
+    ///     let handle = std::thread::spawn(|| NAMES.push("spam".to_string()).0);
+    ///     let (idx, eggs) = NAMES.push("eggs".to_string());
+    ///     let other = handle.join().unwrap();
+    ///     assert_eq!(eggs, "eggs");
+    ///     assert_eq!(NAMES.get(idx), Some(eggs));
+    ///     assert_eq!(NAMES.get(other).map(String::as_str), Some("spam"));
+    ///     assert_eq!(NAMES.len(), 2);
+    /// }
+    /// ```
+    pub struct OnceVec<T> {
+        len: AtomicUsize,
+        segments: [OnceCell<Box<[Slot<T>]>>; SEGMENTS],
+    }
+
+    impl<T> OnceVec<T> {
+        // Only used to initialize the array, which can't be done with a
+        // `Copy` value, as cells aren't `Copy`.
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY_SEGMENT: OnceCell<Box<[Slot<T>]>> = OnceCell::new();
+
+        /// Creates a new empty vector. Doesn't allocate.
+        pub const fn new() -> OnceVec<T> {
+            OnceVec { len: AtomicUsize::new(0), segments: [Self::EMPTY_SEGMENT; SEGMENTS] }
+        }
+
+        /// Returns the number of elements in the vector, including those
+        /// which are still being pushed by other threads.
+        pub fn len(&self) -> usize {
+            self.len.load(Ordering::Acquire)
+        }
+
+        /// Returns `true` if the vector has no elements.
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// Gets the reference to the element at `idx`.
+        ///
+        /// Returns `None` if it is out of bounds, or if another thread is
+        /// still pushing it. This method never blocks.
+        pub fn get(&self, idx: usize) -> Option<&T> {
+            let (segment, offset) = location(idx)?;
+            self.segments[segment].get()?[offset].get()
+        }
+
+        /// Appends `value` to the vector, returning its index and a reference
+        /// to it.
+        ///
+        /// # Panics
+        ///
+        /// Panics if the number of elements overflows a `usize`.
+        pub fn push(&self, value: T) -> (usize, &T) {
+            let idx = self.len.fetch_add(1, Ordering::AcqRel);
+            let (segment, offset) = match location(idx) {
+                Some(it) => it,
+                None => capacity_overflow(),
+            };
+            let segment = self.segments[segment]
+                .get_or_init_as(Self::NAME, || new_segment(segment, Slot::new));
+            // Safe, because `fetch_add` gave us a unique index, so no one else
+            // writes to this slot.
+            let value = unsafe { segment[offset].write(value) };
+            (idx, value)
+        }
+
+        /// Names the vector in the panic of a re-entrant initialization.
+        const NAME: CellName = CellName(|f| write!(f, "sync::OnceVec<{}>", type_name::<T>()));
+    }
+
+    impl<T> Default for OnceVec<T> {
+        fn default() -> OnceVec<T> {
+            OnceVec::new()
+        }
+    }
+
+    impl<T: fmt::Debug> fmt::Debug for OnceVec<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_list().entries((0..self.len()).filter_map(|idx| self.get(idx))).finish()
+        }
+    }
+}
+
+/// A slot of `sync::OnceVec`, which is written at most once.
+#[cfg(once_cell_sync)]
+struct Slot<T> {
+    ready: AtomicBool,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// Same bounds as `sync::OnceCell`: the slot hands out `&T` to all threads,
+// and drops the value on whichever thread drops the vector.
+#[cfg(once_cell_sync)]
+unsafe impl<T: Sync + Send> Sync for Slot<T> {}
+#[cfg(once_cell_sync)]
+unsafe impl<T: Send> Send for Slot<T> {}
+
+#[cfg(once_cell_sync)]
+impl<T> Slot<T> {
+    fn new() -> Slot<T> {
+        Slot { ready: AtomicBool::new(false), value: UnsafeCell::new(MaybeUninit::uninit()) }
+    }
+
+    fn get(&self) -> Option<&T> {
+        // `Acquire` pairs with the `Release` in `write`, so the value is
+        // fully written if we see the flag.
+        if self.ready.load(Ordering::Acquire) {
+            Some(unsafe { &*(*self.value.get()).as_ptr() })
+        } else {
+            None
+        }
+    }
+
+    /// Writes the value. The caller must guarantee that this is the only
+    /// write to the slot.
+    unsafe fn write(&self, value: T) -> &T {
+        let slot = &mut *self.value.get();
+        ptr::write(slot.as_mut_ptr(), value);
+        self.ready.store(true, Ordering::Release);
+        &*slot.as_ptr()
+    }
+}
+
+#[cfg(once_cell_sync)]
+impl<T> Drop for Slot<T> {
+    fn drop(&mut self) {
+        if *self.ready.get_mut() {
+            unsafe { ptr::drop_in_place((*self.value.get()).as_mut_ptr()) }
+        }
+    }
+}
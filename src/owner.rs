@@ -0,0 +1,150 @@
+//! Identifies the threads which run initializers of `sync` cells.
+//!
+//! The thread which runs the initializer of a cell keeps the cell in a
+//! thread-local list, for the duration of the initializer. That's all it
+//! takes to detect re-entrant initialization, without touching any state
+//! shared with other threads.
+//!
+//! To tell other threads who is initializing a cell, the implementations
+//! store a small id of the initializing thread in the cell, in the spare bits
+//! of its state word where they can, and `thread` maps the id back to a
+//! `ThreadId`. Ids are given out on first use, and reused once their thread
+//! exits, so that they stay small.
+
+use std::{
+    cell::{Cell, RefCell},
+    sync::{Mutex, MutexGuard},
+    thread::{self, ThreadId},
+};
+
+use crate::CellName;
+
+/// The largest id, so that ids fit in the spare bits of every implementation.
+const MAX_ID: u32 = (1 << 24) - 1;
+
+/// The threads which have been given an id, indexed by the id. The id `0` is
+/// never given out: it means that the initializing thread is unknown.
+struct Ids {
+    threads: Vec<Option<ThreadId>>,
+    free: Vec<u32>,
+}
+
+static IDS: Mutex<Ids> = Mutex::new(Ids { threads: Vec::new(), free: Vec::new() });
+
+fn ids() -> MutexGuard<'static, Ids> {
+    // We never panic while holding the lock, but there's no reason to
+    // propagate poisoning anyway.
+    IDS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl Ids {
+    fn alloc(&mut self, thread: ThreadId) -> u32 {
+        if let Some(id) = self.free.pop() {
+            self.threads[id as usize] = Some(thread);
+            return id;
+        }
+        if self.threads.is_empty() {
+            self.threads.push(None);
+        }
+        let id = self.threads.len() as u32;
+        if id > MAX_ID {
+            return 0;
+        }
+        self.threads.push(Some(thread));
+        id
+    }
+}
+
+struct Current {
+    /// The id of this thread, once it has asked for one.
+    id: Cell<Option<u32>>,
+    /// The cells this thread is initializing, innermost last.
+    cells: RefCell<Vec<usize>>,
+}
+
+impl Drop for Current {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.get().filter(|&id| id != 0) {
+            let mut ids = ids();
+            ids.threads[id as usize] = None;
+            ids.free.push(id);
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT: Current = const {
+        Current { id: Cell::new(None), cells: RefCell::new(Vec::new()) }
+    };
+}
+
+/// Returns the id of the current thread, which is never more than `2^24 - 1`.
+/// Returns `0` if the thread can't have an id, because it is exiting, or
+/// because there are too many threads.
+pub(crate) fn current_id() -> u32 {
+    CURRENT
+        .try_with(|current| match current.id.get() {
+            Some(id) => id,
+            None => {
+                let id = ids().alloc(thread::current().id());
+                current.id.set(Some(id));
+                id
+            }
+        })
+        .unwrap_or(0)
+}
+
+/// Returns the thread with `id`, as returned by `current_id`, if it is still
+/// running.
+pub(crate) fn thread(id: u32) -> Option<ThreadId> {
+    if id == 0 {
+        return None;
+    }
+    ids().threads.get(id as usize).copied().flatten()
+}
+
+/// Registers the current thread as the one initializing `cell`, until dropped.
+pub(crate) struct Owner {
+    cell: usize,
+}
+
+impl Owner {
+    pub(crate) fn register(cell: usize) -> Owner {
+        let _ = CURRENT.try_with(|current| current.cells.borrow_mut().push(cell));
+        Owner { cell }
+    }
+}
+
+impl Drop for Owner {
+    fn drop(&mut self) {
+        let _ = CURRENT.try_with(|current| {
+            let mut cells = current.cells.borrow_mut();
+            if let Some(idx) = cells.iter().rposition(|&cell| cell == self.cell) {
+                cells.remove(idx);
+            }
+        });
+    }
+}
+
+/// Panics if `cell` is being initialized by the current thread. Must be called
+/// before blocking on `cell`, which would otherwise deadlock.
+///
+/// `name` names the type of the cell, for diagnostics.
+pub(crate) fn check_reentrancy(cell: usize, name: CellName) {
+    // `loom` runs all the threads of a model on a single OS thread, so they
+    // would all share the list.
+    if cfg!(loom) {
+        return;
+    }
+    let reentrant =
+        CURRENT.try_with(|current| current.cells.borrow().contains(&cell)).unwrap_or(false);
+    if reentrant {
+        self::reentrant(name)
+    }
+}
+
+/// Panics because the initializer of the cell `name` accessed the cell itself.
+#[cold]
+pub(crate) fn reentrant(name: CellName) -> ! {
+    panic!("reentrant init of `{}`: the initializer accessed the cell itself", name)
+}
@@ -0,0 +1,54 @@
+//! Blocks the threads which wait for a `sync::OnceCell` of `imp_parked.rs`
+//! with the `futex` system call on its state word, so Linux needs neither
+//! `parking_lot` nor the waiter queue of `imp_std.rs`.
+
+use std::{convert::TryInto, ptr, time::Instant};
+
+use crate::primitives::AtomicU32;
+
+/// Blocks the current thread while `state` holds `expected`, until `deadline`.
+/// Returns on a wakeup, a timeout, a signal, or if the state differs from
+/// `expected` to begin with; the caller re-checks the state in any case.
+pub(crate) fn wait(state: &AtomicU32, expected: u32, deadline: Option<Instant>) {
+    let timeout = match deadline {
+        Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+            Some(timeout) => Some(timeout),
+            None => return,
+        },
+        None => None,
+    };
+    // Durations which don't fit into a `timespec` are as good as no timeout.
+    let timespec = timeout.and_then(|timeout| {
+        Some(libc::timespec {
+            tv_sec: timeout.as_secs().try_into().ok()?,
+            tv_nsec: timeout.subsec_nanos() as _,
+        })
+    });
+    let timespec_ptr = match &timespec {
+        Some(timespec) => timespec as *const libc::timespec,
+        None => ptr::null(),
+    };
+    // The kernel compares the futex word with `expected` atomically with
+    // going to sleep.
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            state as *const AtomicU32,
+            libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+            expected,
+            timespec_ptr,
+        );
+    }
+}
+
+/// Wakes up every thread blocked in `wait` on `state`.
+pub(crate) fn wake_all(state: &AtomicU32) {
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            state as *const AtomicU32,
+            libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+            i32::MAX,
+        );
+    }
+}
@@ -0,0 +1,36 @@
+//! A model of `park_pl.rs` and `park_futex.rs` for `loom`, which can't model
+//! either. Like a parking lot with a single bucket, one mutex guards the
+//! check of the state word and going to sleep, and `wake_all` takes it after
+//! the state changes, so `loom` finds any interleaving in which a waiter
+//! sleeps with nobody left to wake it up.
+
+use std::time::Instant;
+
+use loom::sync::{Condvar, Mutex};
+
+use crate::primitives::{AtomicU32, Ordering};
+
+loom::lazy_static! {
+    static ref BUCKET: (Mutex<()>, Condvar) = (Mutex::new(()), Condvar::new());
+}
+
+/// Blocks the current thread while `state` holds `expected`. `loom` can't
+/// model time, so with a `deadline` this is a spurious wakeup instead.
+pub(crate) fn wait(state: &AtomicU32, expected: u32, deadline: Option<Instant>) {
+    if deadline.is_some() {
+        loom::thread::yield_now();
+        return;
+    }
+    let (lock, condvar) = &*BUCKET;
+    let guard = lock.lock().unwrap();
+    if state.load(Ordering::Relaxed) == expected {
+        drop(condvar.wait(guard).unwrap());
+    }
+}
+
+/// Wakes up every thread blocked in `wait`, on any state word.
+pub(crate) fn wake_all(_state: &AtomicU32) {
+    let (lock, condvar) = &*BUCKET;
+    drop(lock.lock().unwrap());
+    condvar.notify_all();
+}
@@ -0,0 +1,34 @@
+//! Blocks the threads which wait for a `sync::OnceCell` of `imp_parked.rs`
+//! with `parking_lot_core`, keyed by the address of the state word.
+
+use std::time::Instant;
+
+use parking_lot_core::{self, DEFAULT_PARK_TOKEN, DEFAULT_UNPARK_TOKEN};
+
+use crate::primitives::{AtomicU32, Ordering};
+
+/// Blocks the current thread while `state` holds `expected`, until `deadline`.
+/// Returns on a wakeup, a timeout, or if the state differs from `expected` to
+/// begin with; the caller re-checks the state in any case.
+pub(crate) fn wait(state: &AtomicU32, expected: u32, deadline: Option<Instant>) {
+    // `validate` runs under the parking lot's bucket lock, as does
+    // `unpark_all` in `wake_all`, so a change of the state followed by
+    // `wake_all` can't slip in between the check and going to sleep.
+    unsafe {
+        parking_lot_core::park(
+            state as *const AtomicU32 as usize,
+            || state.load(Ordering::Relaxed) == expected,
+            || (),
+            |_, _| (),
+            DEFAULT_PARK_TOKEN,
+            deadline,
+        );
+    }
+}
+
+/// Wakes up every thread blocked in `wait` on `state`.
+pub(crate) fn wake_all(state: &AtomicU32) {
+    unsafe {
+        parking_lot_core::unpark_all(state as *const AtomicU32 as usize, DEFAULT_UNPARK_TOKEN);
+    }
+}
@@ -0,0 +1,140 @@
+//! Poisoning for `sync::OnceCell`.
+//!
+//! Every implementation keeps the `PoisonPolicy` of a cell in its state word,
+//! next to the status of the cell, and poisons the cell by storing the
+//! `POISONED` status when an initializer panics. So a cell needs no room for
+//! poisoning, except with `PoisonPolicy::StorePayload`: then the payload of
+//! the panic is moved into a global table, and the state word of the poisoned
+//! cell holds its index.
+
+#[cfg(feature = "std")]
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::policy::PoisonPolicy;
+
+/// Encodes `policy` into two bits of a state word.
+pub(crate) const fn encode(policy: PoisonPolicy) -> u8 {
+    match policy {
+        PoisonPolicy::Retry => 0,
+        PoisonPolicy::Poison => 1,
+        #[cfg(feature = "std")]
+        PoisonPolicy::StorePayload => 2,
+    }
+}
+
+pub(crate) fn decode(bits: u8) -> PoisonPolicy {
+    match bits {
+        0 => PoisonPolicy::Retry,
+        1 => PoisonPolicy::Poison,
+        #[cfg(feature = "std")]
+        2 => PoisonPolicy::StorePayload,
+        _ => unreachable!(),
+    }
+}
+
+/// Panics because the cell is poisoned. Called by the implementations when
+/// they find the `POISONED` status.
+#[cold]
+pub(crate) fn poisoned() -> ! {
+    panic!("OnceCell instance has previously been poisoned")
+}
+
+/// Runs the initializer `f` of a cell with `policy`. If `f` panics, and the
+/// policy is `StorePayload`, moves the payload into the table, calls
+/// `stored` with its index, and resumes unwinding with a copy of the payload.
+/// Only `&'static str` and `String` payloads can be copied, others are
+/// replaced with a `&'static str` which says so. The caller poisons the cell
+/// while unwinding, with the index in its state.
+pub(crate) fn run<R>(
+    policy: PoisonPolicy,
+    f: impl FnOnce() -> R,
+    #[allow(unused_variables)] stored: impl FnOnce(usize),
+) -> R {
+    match policy {
+        #[cfg(feature = "std")]
+        PoisonPolicy::StorePayload => match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(res) => res,
+            Err(payload) => {
+                let copy: Box<dyn Any + Send> = if let Some(&msg) = payload.downcast_ref::<&str>() {
+                    Box::new(msg)
+                } else if let Some(msg) = payload.downcast_ref::<String>() {
+                    Box::new(msg.clone())
+                } else {
+                    Box::new("OnceCell initializer panicked, see `take_panic_payload`")
+                };
+                stored(Payloads::lock().insert(payload));
+                // The panic hook has already reported the original panic.
+                panic::resume_unwind(copy)
+            }
+        },
+        _ => f(),
+    }
+}
+
+/// Takes the payload at `index`, if `still_poisoned` confirms that the slot
+/// still belongs to the cell which asks for it: otherwise, the poison might
+/// have been cleared, and the slot reused, in the meantime.
+#[cfg(feature = "std")]
+pub(crate) fn take_payload(
+    index: usize,
+    still_poisoned: impl FnOnce() -> bool,
+) -> Option<Box<dyn Any + Send>> {
+    let mut payloads = Payloads::lock();
+    if !still_poisoned() {
+        return None;
+    }
+    payloads.slots[index].take()
+}
+
+/// Drops the payload at `index`, if any, and frees the slot. Must be called
+/// once, by the cell which has stored the payload, after it has cleared the
+/// index from its state.
+#[cfg(feature = "std")]
+pub(crate) fn free_payload(index: usize) {
+    let payload = Payloads::lock().remove(index);
+    // The payload's destructor is user code, which might panic, or poison
+    // another cell: run it without holding the lock.
+    drop(payload);
+}
+
+/// The table of the panic payloads of poisoned cells. Freed slots are reused,
+/// so that the indices stay small.
+#[cfg(feature = "std")]
+struct Payloads {
+    slots: Vec<Option<Box<dyn Any + Send>>>,
+    free: Vec<usize>,
+}
+
+#[cfg(feature = "std")]
+static PAYLOADS: Mutex<Payloads> = Mutex::new(Payloads { slots: Vec::new(), free: Vec::new() });
+
+#[cfg(feature = "std")]
+impl Payloads {
+    fn lock() -> MutexGuard<'static, Payloads> {
+        // We never panic while holding the lock, but there's no reason to
+        // propagate poisoning anyway.
+        PAYLOADS.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn insert(&mut self, payload: Box<dyn Any + Send>) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.slots[index] = Some(payload);
+                index
+            }
+            None => {
+                self.slots.push(Some(payload));
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    fn remove(&mut self, index: usize) -> Option<Box<dyn Any + Send>> {
+        self.free.push(index);
+        self.slots[index].take()
+    }
+}
@@ -0,0 +1,74 @@
+//! Synchronization primitives used by the implementations of `sync`.
+//!
+//! These are the primitives from `core` and `std`, unless the crate is built
+//! with `RUSTFLAGS="--cfg loom"`. Then, they are replaced with the ones from
+//! the `loom` crate, which runs the model tests in `loom_tests.rs` under every
+//! possible interleaving of the threads, and checks the memory orderings.
+//!
+//! `loom`'s primitives can't be created in `const` context, so the public
+//! `sync` API, whose constructors are `const`, is not available with
+//! `--cfg loom`. The model tests use the implementations directly.
+
+// Each implementation uses only some of these.
+#![allow(unused_imports, dead_code)]
+
+#[cfg(not(loom))]
+pub(crate) use core::{
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU8, AtomicUsize, Ordering},
+};
+#[cfg(loom)]
+pub(crate) use loom::{
+    cell::UnsafeCell,
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU8, AtomicUsize, Ordering},
+};
+
+#[cfg(all(feature = "std", not(loom)))]
+pub(crate) use std::{
+    sync::Arc,
+    thread::{self, Thread},
+};
+#[cfg(loom)]
+pub(crate) use loom::{
+    sync::Arc,
+    thread::{self, Thread},
+};
+
+/// Blocks the current thread until it is unparked, or `timeout` passes.
+#[cfg(all(feature = "std", not(loom)))]
+pub(crate) fn park_timeout(timeout: std::time::Duration) {
+    thread::park_timeout(timeout)
+}
+
+/// `loom` can't model time, so this is a spurious wakeup.
+#[cfg(loom)]
+pub(crate) fn park_timeout(_timeout: std::time::Duration) {
+    thread::yield_now()
+}
+
+/// `core::cell::UnsafeCell` with the API of `loom::cell::UnsafeCell`, which
+/// tracks every access to the contents.
+#[cfg(not(loom))]
+pub(crate) struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    pub(crate) const fn new(value: T) -> UnsafeCell<T> {
+        UnsafeCell(core::cell::UnsafeCell::new(value))
+    }
+
+    #[inline]
+    pub(crate) fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    #[inline]
+    pub(crate) fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        self.0.into_inner()
+    }
+}
@@ -0,0 +1,330 @@
+//! "First one wins" flavor of `OnceCell`.
+//!
+//! If two threads race to initialize a type from the `race` module, they
+//! don't block: both execute their initialization functions, but only one of
+//! them stores the result, and both observe that winning value.
+//!
+//! Each cell is a single atomic word which is published with a
+//! compare-exchange, so this module never blocks, works without `std`, and
+//! doesn't depend on the implementation of `sync` selected by Cargo features.
+//! `OnceBox` additionally requires the `alloc` feature.
+//!
+//! All operations use `Acquire` and `Release` orderings: the value published
+//! by a successful `set` or `get_or_init` is fully visible to any thread which
+//! observes it via `get`.
+
+#[cfg(feature = "alloc")]
+pub use self::once_box::OnceBox;
+
+use core::{
+    num::NonZeroUsize,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A thread-safe cell which can be written to only once.
+///
+/// # Example
+/// ```
+/// use std::num::NonZeroUsize;
+/// use once_cell::race::OnceNonZeroUsize;
+///
+/// static PAGE_SIZE: OnceNonZeroUsize = OnceNonZeroUsize::new();
+///
+/// let page_size = PAGE_SIZE.get_or_init(|| NonZeroUsize::new(4096).unwrap());
+/// assert_eq!(page_size.get(), 4096);
+/// ```
+#[derive(Default, Debug)]
+pub struct OnceNonZeroUsize {
+    inner: AtomicUsize,
+}
+
+impl OnceNonZeroUsize {
+    /// Creates a new empty cell.
+    pub const fn new() -> OnceNonZeroUsize {
+        OnceNonZeroUsize { inner: AtomicUsize::new(0) }
+    }
+
+    /// Gets the underlying value.
+    pub fn get(&self) -> Option<NonZeroUsize> {
+        let val = self.inner.load(Ordering::Acquire);
+        NonZeroUsize::new(val)
+    }
+
+    /// Sets the contents of this cell to `value`.
+    ///
+    /// Returns `Ok(())` if the cell was empty and `Err(())` if it was
+    /// full.
+    #[allow(clippy::result_unit_err)]
+    pub fn set(&self, value: NonZeroUsize) -> Result<(), ()> {
+        let exchange =
+            self.inner.compare_exchange(0, value.get(), Ordering::AcqRel, Ordering::Acquire);
+        match exchange {
+            Ok(_) => Ok(()),
+            Err(_) => Err(()),
+        }
+    }
+
+    /// Gets the contents of the cell, initializing it with `f` if the cell was
+    /// empty.
+    ///
+    /// If several threads concurrently run `get_or_init`, more than one `f` can
+    /// be called. However, all threads will return the same value, produced by
+    /// some `f`.
+    pub fn get_or_init<F>(&self, f: F) -> NonZeroUsize
+    where
+        F: FnOnce() -> NonZeroUsize,
+    {
+        enum Void {}
+        match self.get_or_try_init(|| Ok::<NonZeroUsize, Void>(f())) {
+            Ok(val) => val,
+            Err(void) => match void {},
+        }
+    }
+
+    /// Gets the contents of the cell, initializing it with `f` if
+    /// the cell was empty. If the cell was empty and `f` failed, an
+    /// error is returned.
+    ///
+    /// If several threads concurrently run `get_or_init`, more than one `f` can
+    /// be called. However, all threads will return the same value, produced by
+    /// some `f`.
+    pub fn get_or_try_init<F, E>(&self, f: F) -> Result<NonZeroUsize, E>
+    where
+        F: FnOnce() -> Result<NonZeroUsize, E>,
+    {
+        let val = self.inner.load(Ordering::Acquire);
+        let res = match NonZeroUsize::new(val) {
+            Some(it) => it,
+            None => {
+                let mut val = f()?.get();
+                let exchange =
+                    self.inner.compare_exchange(0, val, Ordering::AcqRel, Ordering::Acquire);
+                if let Err(old) = exchange {
+                    val = old;
+                }
+                unsafe { NonZeroUsize::new_unchecked(val) }
+            }
+        };
+        Ok(res)
+    }
+}
+
+/// A thread-safe cell which can be written to only once.
+///
+/// # Example
+/// ```
+/// use once_cell::race::OnceBool;
+///
+/// static VERBOSE: OnceBool = OnceBool::new();
+///
+/// assert!(VERBOSE.get().is_none());
+/// assert_eq!(VERBOSE.set(true), Ok(()));
+/// assert_eq!(VERBOSE.set(false), Err(()));
+/// assert!(VERBOSE.get_or_init(|| false));
+/// ```
+#[derive(Default, Debug)]
+pub struct OnceBool {
+    inner: OnceNonZeroUsize,
+}
+
+impl OnceBool {
+    /// Creates a new empty cell.
+    pub const fn new() -> OnceBool {
+        OnceBool { inner: OnceNonZeroUsize::new() }
+    }
+
+    /// Gets the underlying value.
+    pub fn get(&self) -> Option<bool> {
+        self.inner.get().map(OnceBool::from_usize)
+    }
+
+    /// Sets the contents of this cell to `value`.
+    ///
+    /// Returns `Ok(())` if the cell was empty and `Err(())` if it was
+    /// full.
+    #[allow(clippy::result_unit_err)]
+    pub fn set(&self, value: bool) -> Result<(), ()> {
+        self.inner.set(OnceBool::to_usize(value))
+    }
+
+    /// Gets the contents of the cell, initializing it with `f` if the cell was
+    /// empty.
+    ///
+    /// If several threads concurrently run `get_or_init`, more than one `f` can
+    /// be called. However, all threads will return the same value, produced by
+    /// some `f`.
+    pub fn get_or_init<F>(&self, f: F) -> bool
+    where
+        F: FnOnce() -> bool,
+    {
+        OnceBool::from_usize(self.inner.get_or_init(|| OnceBool::to_usize(f())))
+    }
+
+    /// Gets the contents of the cell, initializing it with `f` if
+    /// the cell was empty. If the cell was empty and `f` failed, an
+    /// error is returned.
+    ///
+    /// If several threads concurrently run `get_or_init`, more than one `f` can
+    /// be called. However, all threads will return the same value, produced by
+    /// some `f`.
+    pub fn get_or_try_init<F, E>(&self, f: F) -> Result<bool, E>
+    where
+        F: FnOnce() -> Result<bool, E>,
+    {
+        self.inner.get_or_try_init(|| f().map(OnceBool::to_usize)).map(OnceBool::from_usize)
+    }
+
+    #[inline]
+    fn from_usize(value: NonZeroUsize) -> bool {
+        value.get() == 1
+    }
+
+    #[inline]
+    fn to_usize(value: bool) -> NonZeroUsize {
+        unsafe { NonZeroUsize::new_unchecked(if value { 1 } else { 2 }) }
+    }
+}
+
+#[cfg(feature = "alloc")]
+mod once_box {
+    use core::{
+        fmt,
+        marker::PhantomData,
+        ptr,
+        sync::atomic::{AtomicPtr, Ordering},
+    };
+
+    use alloc::boxed::Box;
+
+    /// A thread-safe cell which can be written to only once.
+    ///
+    /// The value is stored on the heap, and the cell itself is a single
+    /// pointer. If several threads race to initialize the cell, the boxes
+    /// allocated by the losers are dropped.
+    ///
+    /// Note that this type requires `alloc` Cargo feature.
+    ///
+    /// # Example
+    /// ```
+    /// use once_cell::race::OnceBox;
+    ///
+    /// static CELL: OnceBox<String> = OnceBox::new();
+    ///
+    /// let value = CELL.get_or_init(|| Box::new("Hello, World!".to_string()));
+    /// assert_eq!(value, "Hello, World!");
+    /// assert_eq!(CELL.set(Box::new(String::new())), Err(Box::new(String::new())));
+    /// ```
+    pub struct OnceBox<T> {
+        inner: AtomicPtr<T>,
+        ghost: PhantomData<Option<Box<T>>>,
+    }
+
+    impl<T: fmt::Debug> fmt::Debug for OnceBox<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("OnceBox").field("value", &self.get()).finish()
+        }
+    }
+
+    impl<T> Default for OnceBox<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<T> Drop for OnceBox<T> {
+        fn drop(&mut self) {
+            let ptr = *self.inner.get_mut();
+            if !ptr.is_null() {
+                drop(unsafe { Box::from_raw(ptr) })
+            }
+        }
+    }
+
+    impl<T> OnceBox<T> {
+        /// Creates a new empty cell.
+        pub const fn new() -> OnceBox<T> {
+            OnceBox { inner: AtomicPtr::new(ptr::null_mut()), ghost: PhantomData }
+        }
+
+        /// Gets a reference to the underlying value.
+        pub fn get(&self) -> Option<&T> {
+            let ptr = self.inner.load(Ordering::Acquire);
+            if ptr.is_null() {
+                return None;
+            }
+            // A non-null pointer is published by `set` or `get_or_try_init`
+            // with `Release`, and is never freed while `self` is borrowed.
+            Some(unsafe { &*ptr })
+        }
+
+        /// Sets the contents of this cell to `value`.
+        ///
+        /// Returns `Ok(())` if the cell was empty and `Err(value)` if it was
+        /// full.
+        pub fn set(&self, value: Box<T>) -> Result<(), Box<T>> {
+            let ptr = Box::into_raw(value);
+            let exchange = self.inner.compare_exchange(
+                ptr::null_mut(),
+                ptr,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+            if exchange.is_err() {
+                let value = unsafe { Box::from_raw(ptr) };
+                return Err(value);
+            }
+            Ok(())
+        }
+
+        /// Gets the contents of the cell, initializing it with `f` if the cell was
+        /// empty.
+        ///
+        /// If several threads concurrently run `get_or_init`, more than one `f` can
+        /// be called. However, all threads will return the same value, produced by
+        /// some `f`.
+        pub fn get_or_init<F>(&self, f: F) -> &T
+        where
+            F: FnOnce() -> Box<T>,
+        {
+            enum Void {}
+            match self.get_or_try_init(|| Ok::<Box<T>, Void>(f())) {
+                Ok(val) => val,
+                Err(void) => match void {},
+            }
+        }
+
+        /// Gets the contents of the cell, initializing it with `f` if
+        /// the cell was empty. If the cell was empty and `f` failed, an
+        /// error is returned.
+        ///
+        /// If several threads concurrently run `get_or_init`, more than one `f` can
+        /// be called. However, all threads will return the same value, produced by
+        /// some `f`.
+        pub fn get_or_try_init<F, E>(&self, f: F) -> Result<&T, E>
+        where
+            F: FnOnce() -> Result<Box<T>, E>,
+        {
+            let mut ptr = self.inner.load(Ordering::Acquire);
+
+            if ptr.is_null() {
+                let val = f()?;
+                ptr = Box::into_raw(val);
+                let exchange = self.inner.compare_exchange(
+                    ptr::null_mut(),
+                    ptr,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                );
+                if let Err(old) = exchange {
+                    // Somebody else won the race, drop our box and use theirs.
+                    drop(unsafe { Box::from_raw(ptr) });
+                    ptr = old;
+                }
+            };
+            Ok(unsafe { &*ptr })
+        }
+    }
+
+    unsafe impl<T: Sync + Send> Sync for OnceBox<T> {}
+    unsafe impl<T: Send> Send for OnceBox<T> {}
+}
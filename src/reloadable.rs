@@ -0,0 +1,270 @@
+use std::{
+    cell::Cell,
+    fmt,
+    marker::PhantomData,
+    ops::Deref,
+    ptr,
+    sync::{
+        atomic::{AtomicPtr, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+};
+
+/// A thread-safe cell, whose value can be atomically replaced.
+///
+/// Unlike `OnceCell`, the value can be changed after it is set: `reload`
+/// builds a new value and publishes it, and all subsequent calls to `get`
+/// return the new value. Readers which hold a guard to the old value keep
+/// using it, and the old value is dropped together with the last guard.
+///
+/// `get` doesn't lock, and never waits for `reload`. It is heavier than
+/// `OnceCell::get`, which is a single load, though: it stores the value into
+/// a slot owned by the current thread, with a full memory barrier, loads the
+/// value again, and clones an `Arc`, whose reference count is shared by all
+/// the readers of the value. `reload` doesn't wait for readers to drop their
+/// guards, but it may briefly wait for the readers which are in the middle of
+/// `get`.
+///
+/// Note that this type requires the `std` feature.
+///
+/// # Example
+/// ```
+/// use once_cell::sync::{Lazy, Reloadable};
+///
+/// static CONFIG: Lazy<Reloadable<String>> = Lazy::new(|| Reloadable::new("v1".to_string()));
+///
+/// fn main() {
+///     let config = CONFIG.get();
+///     let old = CONFIG.reload(|| "v2".to_string());
+///     assert_eq!(*old, "v1");
+///     // The old guard still sees the old value.
+///     assert_eq!(*config, "v1");
+///     assert_eq!(*CONFIG.get(), "v2");
+/// }
+/// ```
+pub struct Reloadable<T> {
+    /// The current value, which came from `Arc::into_raw`.
+    value: AtomicPtr<T>,
+    /// Serializes writers.
+    writer: Mutex<()>,
+    // We hand out `Arc<T>`s, so we need the same bounds as `Arc`.
+    _marker: PhantomData<Arc<T>>,
+}
+
+/// A reference to a value of `Reloadable`, which stays valid after the value
+/// is replaced.
+pub struct ReloadableGuard<T> {
+    value: Arc<T>,
+}
+
+impl<T> Reloadable<T> {
+    /// Creates a new cell with the given value.
+    pub fn new(value: T) -> Reloadable<T> {
+        Reloadable {
+            value: AtomicPtr::new(Arc::into_raw(Arc::new(value)) as *mut T),
+            writer: Mutex::new(()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Gets the current value.
+    ///
+    /// The returned guard keeps the value alive, even if it is replaced by
+    /// `reload` in the meantime.
+    pub fn get(&self) -> ReloadableGuard<T> {
+        let ptr = match HAZARD.try_with(|hazard| self.acquire(hazard.slot())) {
+            Ok(ptr) => ptr,
+            // The current thread is exiting, and its slot is gone. Exclude
+            // the writers instead, which drop the values they replace while
+            // holding the lock.
+            Err(_) => {
+                let _writer = self.lock_writer();
+                let ptr = self.value.load(Ordering::Acquire);
+                unsafe { Arc::increment_strong_count(ptr) };
+                ptr
+            }
+        };
+        // Safe, because we own the reference we've just counted.
+        ReloadableGuard { value: unsafe { Arc::from_raw(ptr) } }
+    }
+
+    /// Replaces the value with the one returned by `f`, and returns the
+    /// previous value.
+    ///
+    /// `f` runs without any locks held, so it may call `get`. If several
+    /// threads reload concurrently, their values are published one by one.
+    pub fn reload<F>(&self, f: F) -> ReloadableGuard<T>
+    where
+        F: FnOnce() -> T,
+    {
+        self.publish(f())
+    }
+
+    /// Replaces the value with the one returned by `f`, and returns the
+    /// previous value. If `f` fails, the error is returned, and the value
+    /// stays the same.
+    pub fn try_reload<F, E>(&self, f: F) -> Result<ReloadableGuard<T>, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        let value = f()?;
+        Ok(self.publish(value))
+    }
+
+    /// Loads the value, and increments its reference count. In between, the
+    /// value is protected from being dropped by the hazard `slot`.
+    fn acquire(&self, slot: &AtomicPtr<()>) -> *const T {
+        let mut ptr = self.value.load(Ordering::Acquire);
+        loop {
+            // `SeqCst` keeps the store before the load below, and pairs with
+            // the swap and the loads in `publish`: if `ptr` is still the
+            // value after we've stored it into the slot, the writer which
+            // replaces it will find it there.
+            slot.store(ptr as *mut (), Ordering::SeqCst);
+            let current = self.value.load(Ordering::SeqCst);
+            if current == ptr {
+                break;
+            }
+            ptr = current;
+        }
+        unsafe { Arc::increment_strong_count(ptr) };
+        // `Release` pairs with the loads in `publish`, so that the writer
+        // which finds the slot cleared doesn't drop the last reference.
+        slot.store(ptr::null_mut(), Ordering::Release);
+        ptr
+    }
+
+    fn publish(&self, value: T) -> ReloadableGuard<T> {
+        let new = Arc::into_raw(Arc::new(value)) as *mut T;
+        let _writer = self.lock_writer();
+        let old = self.value.swap(new, Ordering::SeqCst);
+        // A reader which may still increment the count of `old` has it in its
+        // slot. Wait for it to finish: new readers can't load `old` anymore,
+        // so it doesn't take long. The threads which get a slot after we've
+        // copied them can't load `old` either. We don't hold the lock while
+        // waiting, as it is shared by all the cells.
+        let slots = slots().all.clone();
+        for slot in slots {
+            while slot.load(Ordering::SeqCst) == old as *mut () {
+                thread::yield_now();
+            }
+        }
+        // Safe, because no reader can observe `old` anymore, and all the
+        // guards to it have their own reference counts.
+        ReloadableGuard { value: unsafe { Arc::from_raw(old) } }
+    }
+
+    fn lock_writer(&self) -> MutexGuard<'_, ()> {
+        // We never panic while holding the lock, but there's no reason to
+        // propagate poisoning anyway.
+        self.writer.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// The hazard slots of all the threads which have called `get`. A reader
+/// stores the value it is about to count a reference to into its slot, so
+/// that the writers don't drop the value in between. The slots are shared by
+/// all the cells, never freed, and reused once their thread exits.
+struct Slots {
+    all: Vec<&'static AtomicPtr<()>>,
+    free: Vec<&'static AtomicPtr<()>>,
+}
+
+static SLOTS: Mutex<Slots> = Mutex::new(Slots { all: Vec::new(), free: Vec::new() });
+
+fn slots() -> MutexGuard<'static, Slots> {
+    // We never panic while holding the lock, but there's no reason to
+    // propagate poisoning anyway.
+    SLOTS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+struct Hazard {
+    /// The slot of this thread, once it has called `get`.
+    slot: Cell<Option<&'static AtomicPtr<()>>>,
+}
+
+impl Hazard {
+    fn slot(&self) -> &'static AtomicPtr<()> {
+        if let Some(slot) = self.slot.get() {
+            return slot;
+        }
+        let mut slots = slots();
+        let slot = match slots.free.pop() {
+            Some(slot) => slot,
+            None => {
+                let slot: &'static AtomicPtr<()> =
+                    Box::leak(Box::new(AtomicPtr::new(ptr::null_mut())));
+                slots.all.push(slot);
+                slot
+            }
+        };
+        self.slot.set(Some(slot));
+        slot
+    }
+}
+
+impl Drop for Hazard {
+    fn drop(&mut self) {
+        // The slot is empty outside of `get`.
+        if let Some(slot) = self.slot.get() {
+            slots().free.push(slot);
+        }
+    }
+}
+
+thread_local! {
+    static HAZARD: Hazard = const { Hazard { slot: Cell::new(None) } };
+}
+
+impl<T> Drop for Reloadable<T> {
+    fn drop(&mut self) {
+        // Safe, because the pointer came from `Arc::into_raw`, and we own
+        // one reference.
+        drop(unsafe { Arc::from_raw(*self.value.get_mut()) })
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Reloadable<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Reloadable").field(&*self.get()).finish()
+    }
+}
+
+impl<T: Default> Default for Reloadable<T> {
+    fn default() -> Reloadable<T> {
+        Reloadable::new(T::default())
+    }
+}
+
+impl<T> From<T> for Reloadable<T> {
+    fn from(value: T) -> Reloadable<T> {
+        Reloadable::new(value)
+    }
+}
+
+impl<T> ReloadableGuard<T> {
+    /// Converts the guard into the `Arc` it holds.
+    pub fn into_arc(this: ReloadableGuard<T>) -> Arc<T> {
+        this.value
+    }
+}
+
+impl<T> Deref for ReloadableGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> Clone for ReloadableGuard<T> {
+    fn clone(&self) -> ReloadableGuard<T> {
+        ReloadableGuard { value: Arc::clone(&self.value) }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ReloadableGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
@@ -0,0 +1,62 @@
+//! `serde` support, enabled by the `serde` Cargo feature.
+//!
+//! Cells are serialized as `Option<T>`: `None` if the cell is empty. A `Lazy`
+//! is forced, and serialized as the value itself. Deserializing a cell
+//! produces a cell which is already initialized, or an empty one for `None`.
+//! `Lazy` can't be deserialized, as there's no way to deserialize its
+//! initializing function.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::unsync;
+
+impl<T: Serialize> Serialize for unsync::OnceCell<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.get().serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for unsync::OnceCell<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let cell = unsync::OnceCell::new();
+        if let Some(value) = Option::<T>::deserialize(deserializer)? {
+            let _ = cell.set(value);
+        }
+        Ok(cell)
+    }
+}
+
+impl<T: Serialize, F: FnOnce() -> T> Serialize for unsync::Lazy<T, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        unsync::Lazy::force(self).serialize(serializer)
+    }
+}
+
+#[cfg(once_cell_sync)]
+mod sync_impls {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::sync::{self, Lock};
+
+    impl<T: Serialize, R: Lock> Serialize for sync::OnceCellWith<T, R> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.get().serialize(serializer)
+        }
+    }
+
+    impl<'de, T: Deserialize<'de>, R: Lock> Deserialize<'de> for sync::OnceCellWith<T, R> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let cell = sync::OnceCellWith::new();
+            if let Some(value) = Option::<T>::deserialize(deserializer)? {
+                let _ = cell.set(value);
+            }
+            Ok(cell)
+        }
+    }
+
+    impl<T: Serialize, R: Lock, F: FnOnce() -> T> Serialize for sync::LazyWith<T, R, F> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            sync::LazyWith::force(self).serialize(serializer)
+        }
+    }
+}
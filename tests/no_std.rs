@@ -0,0 +1,79 @@
+//! Checks that the cells are usable from a `#![no_std]` crate.
+//!
+//! Run with `cargo test --no-default-features --features spin`, to make sure
+//! that the library itself is built without `std` as well.
+#![no_std]
+#![cfg(not(loom))]
+
+extern crate std;
+
+use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+use once_cell::unsync;
+
+#[test]
+fn unsync_once_cell() {
+    let c = unsync::OnceCell::new();
+    assert!(c.get().is_none());
+    assert_eq!(c.get_or_try_init(|| Err(())), Err(()));
+    assert_eq!(c.get_or_init(|| 92), &92);
+    assert_eq!(c.set(62), Err(62));
+    assert_eq!(c.into_inner(), Some(92));
+}
+
+#[test]
+fn unsync_lazy() {
+    let called = AtomicUsize::new(0);
+    let x = unsync::Lazy::new(|| {
+        called.fetch_add(1, SeqCst);
+        92
+    });
+    assert_eq!(called.load(SeqCst), 0);
+    assert_eq!(*x, 92);
+    assert_eq!(*x, 92);
+    assert_eq!(called.load(SeqCst), 1);
+}
+
+#[cfg(once_cell_sync)]
+mod sync {
+    use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+    use crossbeam_utils::thread::scope;
+
+    use once_cell::sync::{Lazy, OnceCell};
+
+    #[test]
+    fn static_once_cell() {
+        static CELL: OnceCell<usize> = OnceCell::new();
+        static CALLED: AtomicUsize = AtomicUsize::new(0);
+
+        scope(|scope| {
+            for i in 0..8 {
+                scope.spawn(move |_| {
+                    let &value = CELL.get_or_init(|| {
+                        CALLED.fetch_add(1, SeqCst);
+                        i
+                    });
+                    assert!(value < 8);
+                });
+            }
+        })
+        .unwrap();
+        assert_eq!(CALLED.load(SeqCst), 1);
+    }
+
+    #[test]
+    fn static_lazy() {
+        static XS: Lazy<[i32; 3]> = Lazy::new(|| [1, 2, 3]);
+        assert_eq!(XS.iter().sum::<i32>(), 6);
+    }
+
+    #[test]
+    fn get_or_try_init() {
+        let cell: OnceCell<u32> = OnceCell::new();
+        assert_eq!(cell.get_or_try_init(|| Err(())), Err(()));
+        assert!(cell.get().is_none());
+        assert_eq!(cell.get_or_try_init(|| Ok::<_, ()>(92)), Ok(&92));
+        assert_eq!(cell.set(62), Err(62));
+    }
+}
@@ -1,14 +1,22 @@
+// The public `sync` API is compiled out under `loom`, see `src/loom_tests.rs`.
+#![cfg(not(loom))]
+// Some tests predate these lints, and are kept as they were written.
+#![allow(clippy::unused_unit, clippy::vec_init_then_push)]
+
 use std::{
-    mem, thread, ptr,
     cell::Cell,
     sync::atomic::{AtomicUsize, Ordering::SeqCst},
-    sync::Barrier,
 };
+#[cfg(once_cell_sync)]
+use std::{mem, thread, ptr, sync::Barrier};
 
 use crossbeam_utils::thread::scope;
 
-use once_cell::{sync, unsync};
+use once_cell::{policy, race, unsync};
+#[cfg(once_cell_sync)]
+use once_cell::sync;
 
+#[cfg(once_cell_sync)]
 fn go<F: FnOnce() -> ()>(mut f: F) {
     struct Yolo<T>(T);
     unsafe impl<T> Send for Yolo<T> {}
@@ -36,6 +44,7 @@ fn unsync_once_cell() {
 }
 
 #[test]
+#[cfg(once_cell_sync)]
 fn sync_once_cell() {
     let c = sync::OnceCell::new();
     assert!(c.get().is_none());
@@ -65,6 +74,7 @@ fn unsync_once_cell_drop() {
 }
 
 #[test]
+#[cfg(once_cell_sync)]
 fn sync_once_cell_drop() {
     static DROP_CNT: AtomicUsize = AtomicUsize::new(0);
     struct Dropper;
@@ -90,11 +100,26 @@ fn unsync_once_cell_drop_empty() {
 }
 
 #[test]
+#[cfg(once_cell_sync)]
 fn sync_once_cell_drop_empty() {
     let x = sync::OnceCell::<String>::new();
     drop(x);
 }
 
+#[test]
+#[cfg(all(
+    once_cell_sync,
+    any(
+        all(feature = "std", feature = "parking_lot"),
+        all(feature = "futex", target_os = "linux")
+    )
+))]
+fn sync_once_cell_size() {
+    // The `u32` state word, and the value.
+    assert_eq!(mem::size_of::<sync::OnceCell<u32>>(), 2 * mem::size_of::<u32>());
+    assert_eq!(mem::size_of::<sync::OnceCell<u8>>(), 2 * mem::size_of::<u32>());
+}
+
 #[test]
 fn unsync_lazy_new() {
     let called = Cell::new(0);
@@ -115,6 +140,7 @@ fn unsync_lazy_new() {
 }
 
 #[test]
+#[cfg(once_cell_sync)]
 fn sync_lazy_new() {
     let called = AtomicUsize::new(0);
     let x = sync::Lazy::new(|| {
@@ -136,6 +162,133 @@ fn sync_lazy_new() {
 }
 
 #[test]
+fn unsync_lazy_fn_once() {
+    let xs = vec![1, 2, 3];
+    let lazy: unsync::Lazy<Vec<i32>, _> = unsync::Lazy::new(move || xs);
+    assert_eq!(&*lazy, &vec![1, 2, 3]);
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn sync_lazy_fn_once() {
+    let xs = vec![1, 2, 3];
+    let lazy: sync::Lazy<Vec<i32>, _> = sync::Lazy::new(move || xs);
+    go(|| assert_eq!(&*lazy, &vec![1, 2, 3]));
+    assert_eq!(&*lazy, &vec![1, 2, 3]);
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn lazy_drops_init_after_force() {
+    static DROP_CNT: AtomicUsize = AtomicUsize::new(0);
+    struct Dropper;
+    impl Drop for Dropper {
+        fn drop(&mut self) {
+            DROP_CNT.fetch_add(1, SeqCst);
+        }
+    }
+
+    let dropper = Dropper;
+    let lazy = unsync::Lazy::new(move || {
+        let _dropper = dropper;
+        92
+    });
+    assert_eq!(DROP_CNT.load(SeqCst), 0);
+    assert_eq!(*lazy, 92);
+    assert_eq!(DROP_CNT.load(SeqCst), 1);
+
+    let dropper = Dropper;
+    let lazy = sync::Lazy::new(move || {
+        let _dropper = dropper;
+        92
+    });
+    assert_eq!(DROP_CNT.load(SeqCst), 1);
+    assert_eq!(*lazy, 92);
+    assert_eq!(DROP_CNT.load(SeqCst), 2);
+}
+
+#[test]
+fn unsync_lazy_poisoning() {
+    let x: unsync::Lazy<String> = unsync::Lazy::new(|| panic!("kaboom"));
+    for _ in 0..2 {
+        let res = std::panic::catch_unwind(|| x.len());
+        assert!(res.is_err());
+    }
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn sync_lazy_poisoning() {
+    let x: sync::Lazy<String> = sync::Lazy::new(|| panic!("kaboom"));
+    for _ in 0..2 {
+        let res = std::panic::catch_unwind(|| x.len());
+        assert!(res.is_err());
+    }
+    let res = scope(|scope| scope.spawn(|_| x.len()).join()).unwrap();
+    assert!(res.is_err());
+}
+
+#[test]
+fn unsync_lazy_get_does_not_force() {
+    let called = Cell::new(0);
+    let mut x = unsync::Lazy::new(|| {
+        called.set(called.get() + 1);
+        vec![92]
+    });
+    assert_eq!(unsync::Lazy::get(&x), None);
+    assert_eq!(unsync::Lazy::get_mut(&mut x), None);
+    assert_eq!(called.get(), 0);
+
+    // Methods of the target type are not shadowed.
+    assert_eq!(x.get(..1), Some(&[92][..]));
+    assert_eq!(called.get(), 1);
+    unsync::Lazy::get_mut(&mut x).unwrap().push(62);
+    assert_eq!(unsync::Lazy::get(&x), Some(&vec![92, 62]));
+    assert_eq!(unsync::Lazy::into_value(x).ok(), Some(vec![92, 62]));
+    assert_eq!(called.get(), 1);
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn sync_lazy_get_does_not_force() {
+    let called = AtomicUsize::new(0);
+    let mut x = sync::Lazy::new(|| {
+        called.fetch_add(1, SeqCst);
+        vec![92]
+    });
+    go(|| assert_eq!(sync::Lazy::get(&x), None));
+    assert_eq!(sync::Lazy::get_mut(&mut x), None);
+    assert_eq!(called.load(SeqCst), 0);
+
+    go(|| assert_eq!(x.first(), Some(&92)));
+    assert_eq!(called.load(SeqCst), 1);
+    sync::Lazy::get_mut(&mut x).unwrap().push(62);
+    assert_eq!(sync::Lazy::get(&x), Some(&vec![92, 62]));
+    assert_eq!(sync::Lazy::into_value(x).ok(), Some(vec![92, 62]));
+    assert_eq!(called.load(SeqCst), 1);
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn lazy_into_value_returns_init() {
+    let xs = vec![1, 2, 3];
+    let lazy: unsync::Lazy<Vec<i32>, _> = unsync::Lazy::new(move || xs);
+    let init = unsync::Lazy::into_value(lazy).unwrap_err();
+    assert_eq!(init(), vec![1, 2, 3]);
+
+    let xs = vec![1, 2, 3];
+    let lazy: sync::Lazy<Vec<i32>, _> = sync::Lazy::new(move || xs);
+    let init = sync::Lazy::into_value(lazy).unwrap_err();
+    assert_eq!(init(), vec![1, 2, 3]);
+
+    let lazy: sync::Lazy<i32> = sync::Lazy::new(|| panic!("kaboom"));
+    assert!(std::panic::catch_unwind(|| *lazy).is_err());
+    let res = std::panic::catch_unwind(|| sync::Lazy::into_value(lazy));
+    assert!(panic_message(res.unwrap_err()).contains("poisoned"));
+}
+
+#[test]
+#[cfg(once_cell_sync)]
 fn static_lazy() {
     static XS: sync::Lazy<Vec<i32>> = sync::Lazy::new(|| {
         let mut xs = Vec::new();
@@ -151,6 +304,7 @@ fn static_lazy() {
 }
 
 #[test]
+#[cfg(once_cell_sync)]
 fn static_lazy_no_macros() {
     fn xs() -> &'static Vec<i32> {
         static XS: sync::OnceCell<Vec<i32>> = sync::OnceCell::new();
@@ -166,12 +320,14 @@ fn static_lazy_no_macros() {
 }
 
 #[test]
+#[cfg(once_cell_sync)]
 fn sync_once_cell_is_sync_send() {
     fn assert_traits<T: Send + Sync>() {}
     assert_traits::<sync::OnceCell<String>>();
 }
 
 #[test]
+#[cfg(once_cell_sync)]
 fn eval_once_macro() {
     macro_rules! eval_once {
         (|| -> $ty:ty {
@@ -199,6 +355,7 @@ fn eval_once_macro() {
 }
 
 #[test]
+#[cfg(once_cell_sync)]
 fn sync_once_cell_does_not_leak_partially_constructed_boxes() {
     let n_tries = 100;
     let n_readers = 10;
@@ -225,6 +382,7 @@ fn sync_once_cell_does_not_leak_partially_constructed_boxes() {
 }
 
 #[test]
+#[cfg(once_cell_sync)]
 fn sync_get_does_not_block() {
     let cell = sync::OnceCell::new();
     let barrier = Barrier::new(2);
@@ -244,6 +402,129 @@ fn sync_get_does_not_block() {
     assert_eq!(cell.get(), Some(&"hello".to_string()));
 }
 
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => payload.downcast_ref::<&str>().unwrap().to_string(),
+    }
+}
+
+#[test]
+fn unsync_reentrant_init() {
+    let cell: unsync::OnceCell<Box<i32>> = unsync::OnceCell::new();
+    let res = std::panic::catch_unwind(|| {
+        cell.get_or_init(|| {
+            let _ = cell.get_or_init(|| Box::new(1));
+            Box::new(2)
+        });
+    });
+    let msg = panic_message(res.unwrap_err());
+    assert!(
+        msg.contains("reentrant init of `unsync::OnceCell<alloc::boxed::Box<i32>>`"),
+        "{}",
+        msg
+    );
+
+    let res = std::panic::catch_unwind(|| {
+        cell.get_or_init(|| {
+            let _ = cell.set(Box::new(1));
+            Box::new(2)
+        });
+    });
+    assert!(panic_message(res.unwrap_err()).contains("reentrant init"));
+
+    assert_eq!(cell.get(), None);
+    assert_eq!(**cell.get_or_init(|| Box::new(92)), 92);
+}
+
+#[test]
+fn unsync_lazy_reentrant_init() {
+    thread_local! {
+        static LAZY: unsync::Lazy<i32> = unsync::Lazy::new(|| LAZY.with(|it| **it + 1));
+    }
+    let res = std::panic::catch_unwind(|| LAZY.with(|it| **it));
+    assert!(panic_message(res.unwrap_err()).contains("reentrant init"));
+}
+
+// Without `std`, the `spin` implementation can't identify the current thread
+// and spins forever.
+#[test]
+#[cfg(feature = "std")]
+fn sync_reentrant_init() {
+    let cell: sync::OnceCell<Box<i32>> = sync::OnceCell::new();
+    let res = std::panic::catch_unwind(|| {
+        cell.get_or_init(|| {
+            let _ = cell.get_or_init(|| Box::new(1));
+            Box::new(2)
+        });
+    });
+    let msg = panic_message(res.unwrap_err());
+    assert!(msg.contains("reentrant init of `sync::OnceCell<alloc::boxed::Box<i32>>`"), "{}", msg);
+
+    let res = std::panic::catch_unwind(|| {
+        cell.get_or_init(|| {
+            let _ = cell.set(Box::new(1));
+            Box::new(2)
+        });
+    });
+    assert!(panic_message(res.unwrap_err()).contains("reentrant init"));
+
+    // Other threads are not affected, and block as usual.
+    assert_eq!(cell.get(), None);
+    let barrier = Barrier::new(2);
+    scope(|scope| {
+        scope.spawn(|_| {
+            cell.get_or_init(|| {
+                barrier.wait();
+                Box::new(92)
+            })
+        });
+        barrier.wait();
+        assert_eq!(**cell.get_or_init(|| Box::new(62)), 92);
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_lazy_reentrant_init() {
+    static LAZY: sync::Lazy<i32> = sync::Lazy::new(|| *LAZY + 1);
+    let res = std::panic::catch_unwind(|| *LAZY);
+    let msg = panic_message(res.unwrap_err());
+    assert!(msg.contains("reentrant init of `sync::Lazy<i32>`"), "{}", msg);
+
+    let map: sync::OnceMap<&str, i32> = sync::OnceMap::new();
+    let res = std::panic::catch_unwind(|| *map.get_or_init("a", || *map.get_or_init("a", || 92)));
+    let msg = panic_message(res.unwrap_err());
+    assert!(msg.contains("reentrant init of `sync::OnceMap<&str, i32>`"), "{}", msg);
+}
+
+// The `critical-section` implementation runs initializers inside a critical
+// section, which the `std` implementation of critical sections lets the same
+// thread enter again. Re-entrancy is detected without identifying threads.
+#[test]
+#[cfg(all(feature = "critical-section", not(feature = "std")))]
+fn sync_critical_section_reentrant_init() {
+    let (outer, inner) = (sync::OnceCell::new(), sync::OnceCell::new());
+    // Initializing another cell nests critical sections.
+    assert_eq!(*outer.get_or_init(|| *inner.get_or_init(|| 92) + 1), 93);
+
+    let cell: sync::OnceCell<i32> = sync::OnceCell::new();
+    let res = std::panic::catch_unwind(|| {
+        cell.get_or_init(|| {
+            let _ = cell.get_or_init(|| 1);
+            2
+        });
+    });
+    assert!(panic_message(res.unwrap_err()).contains("reentrant init"));
+    let res = std::panic::catch_unwind(|| *cell.get_or_init(|| *cell.wait()));
+    assert!(panic_message(res.unwrap_err()).contains("reentrant init"));
+
+    // Unwinding leaves the critical section, so other threads can initialize
+    // the cell.
+    assert_eq!(thread::spawn(move || *cell.get_or_init(|| 92)).join().unwrap(), 92);
+}
+
 #[test]
 fn unsync_clone() {
     let s = unsync::OnceCell::new();
@@ -256,6 +537,7 @@ fn unsync_clone() {
 }
 
 #[test]
+#[cfg(once_cell_sync)]
 fn sync_clone() {
     let s = sync::OnceCell::new();
     let c = s.clone();
@@ -267,7 +549,7 @@ fn sync_clone() {
 }
 
 #[test]
-#[cfg(feature = "parking_lot")]
+#[cfg(once_cell_sync)]
 fn sync_get_or_try_init() {
     let cell: sync::OnceCell<String> = sync::OnceCell::new();
     assert!(cell.get().is_none());
@@ -283,40 +565,1446 @@ fn sync_get_or_try_init() {
 }
 
 #[test]
-fn from_impl() {
-    assert_eq!(sync::OnceCell::from("value").get(), Some(&"value"));
-    assert_eq!(unsync::OnceCell::from("value").get(), Some(&"value"));
-    assert_ne!(sync::OnceCell::from("foo").get(), Some(&"bar"));
-    assert_ne!(unsync::OnceCell::from("foo").get(), Some(&"bar"));
+#[cfg(once_cell_sync)]
+fn sync_get_or_try_init_contended() {
+    let cell: sync::OnceCell<usize> = sync::OnceCell::new();
+    let n_calls = AtomicUsize::new(0);
+    scope(|scope| {
+        for i in 0..8 {
+            let cell = &cell;
+            let n_calls = &n_calls;
+            scope.spawn(move |_| {
+                let res = cell.get_or_try_init(|| {
+                    // The first initializer fails, all others must be retried.
+                    if n_calls.fetch_add(1, SeqCst) == 0 {
+                        Err(())
+                    } else {
+                        Ok(i)
+                    }
+                });
+                if let Ok(&value) = res {
+                    assert!(value < 8);
+                }
+            });
+        }
+    })
+    .unwrap();
+    assert!(cell.get().is_some());
+    assert_eq!(cell.get_or_try_init(|| Err(())), Ok(cell.get().unwrap()));
 }
 
 #[test]
-fn partialeq_impl() {
-    assert!(sync::OnceCell::from("value") == sync::OnceCell::from("value"));
-    assert!(sync::OnceCell::from("foo") != sync::OnceCell::from("bar"));
-    assert!(unsync::OnceCell::from("value") == unsync::OnceCell::from("value"));
-    assert!(unsync::OnceCell::from("foo") != unsync::OnceCell::from("bar"));
+#[cfg(once_cell_sync)]
+fn sync_get_or_try_init_all_fail() {
+    // Every initializer fails, so the cell is never set. Threads which wait
+    // for a failing initializer must still be woken up, and retry.
+    for _ in 0..1000 {
+        let cell: sync::OnceCell<i32> = sync::OnceCell::new();
+        scope(|scope| {
+            for _ in 0..2 {
+                let cell = &cell;
+                scope.spawn(move |_| {
+                    let res = cell.get_or_try_init(|| {
+                        thread::yield_now();
+                        Err(())
+                    });
+                    assert_eq!(res, Err(()));
+                });
+            }
+        })
+        .unwrap();
+        assert!(cell.get().is_none());
+    }
+}
 
-    assert!(sync::OnceCell::<String>::new() == sync::OnceCell::new());
-    assert!(sync::OnceCell::<String>::new() != sync::OnceCell::from("value".to_owned()));
-    assert!(unsync::OnceCell::<String>::new() == unsync::OnceCell::new());
-    assert!(unsync::OnceCell::<String>::new() != unsync::OnceCell::from("value".to_owned()));
+#[test]
+#[cfg(once_cell_sync)]
+fn sync_wait() {
+    let cell: sync::OnceCell<String> = sync::OnceCell::new();
+    let barrier = Barrier::new(5);
+    scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|_| {
+                barrier.wait();
+                assert_eq!(cell.wait(), "hello");
+            });
+        }
+        barrier.wait();
+        cell.set("hello".to_string()).unwrap();
+    })
+    .unwrap();
+    assert_eq!(cell.wait(), "hello");
 }
 
 #[test]
-fn unsync_into_inner() {
-    let cell: unsync::OnceCell<String> = unsync::OnceCell::new();
-    assert_eq!(cell.into_inner(), None);
+#[cfg(feature = "std")]
+fn sync_wait_timeout() {
+    use std::time::{Duration, Instant};
+
+    let cell: sync::OnceCell<i32> = sync::OnceCell::new();
+    let start = Instant::now();
+    assert_eq!(cell.wait_timeout(Duration::from_millis(50)), None);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    scope(|scope| {
+        // Waiters which time out must not interfere with the ones which don't.
+        for _ in 0..4 {
+            scope.spawn(|_| assert_eq!(cell.wait_timeout(Duration::from_millis(1)), None));
+        }
+        let waiter = scope.spawn(|_| *cell.wait_timeout(Duration::from_secs(60)).unwrap());
+        thread::sleep(Duration::from_millis(50));
+        cell.get_or_init(|| 92);
+        assert_eq!(waiter.join().unwrap(), 92);
+    })
+    .unwrap();
+    assert_eq!(cell.wait_timeout(Duration::from_secs(0)), Some(&92));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_wait_survives_failed_init() {
+    use std::time::Duration;
+
+    let cell: sync::OnceCell<i32> = sync::OnceCell::new();
+    scope(|scope| {
+        let waiter = scope.spawn(|_| *cell.wait());
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(cell.get_or_try_init(|| Err(())), Err(()));
+        thread::sleep(Duration::from_millis(50));
+        cell.set(92).unwrap();
+        assert_eq!(waiter.join().unwrap(), 92);
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_get_or_try_init_timeout() {
+    use once_cell::WaitError;
+    use std::time::Duration;
+
+    let cell: sync::OnceCell<i32> = sync::OnceCell::new();
+    // Nobody else is initializing the cell, so there's nothing to time out on.
+    let res = cell.get_or_try_init_timeout(Duration::from_secs(0), || Err("nope"));
+    assert_eq!(res, Err(WaitError::Init("nope")));
+    assert_eq!(cell.get(), None);
+
+    let (started, finish) = (Barrier::new(2), Barrier::new(2));
+    scope(|scope| {
+        let initializer = scope.spawn(|_| {
+            *cell.get_or_init(|| {
+                started.wait();
+                finish.wait();
+                92
+            })
+        });
+        started.wait();
+        let res = cell.get_or_try_init_timeout(Duration::from_millis(50), || Ok::<_, ()>(62));
+        assert_eq!(res, Err(WaitError::Timeout));
+        // Giving up doesn't affect the initializer.
+        finish.wait();
+        assert_eq!(initializer.join().unwrap(), 92);
+    })
+    .unwrap();
+    assert_eq!(cell.get_or_try_init_timeout(Duration::from_secs(0), || Ok::<_, ()>(62)), Ok(&92));
+}
+
+// Other threads wait for the initializer outside of the critical section, and
+// aren't mistaken for the initializer.
+#[test]
+#[cfg(all(feature = "critical-section", not(feature = "std")))]
+fn sync_critical_section_wait() {
+    use std::time::Duration;
+
+    let cell: sync::OnceCell<i32> = sync::OnceCell::new();
+    let started = Barrier::new(2);
+    scope(|scope| {
+        scope.spawn(|_| {
+            cell.get_or_init(|| {
+                started.wait();
+                thread::sleep(Duration::from_millis(50));
+                92
+            })
+        });
+        started.wait();
+        assert_eq!(cell.wait(), &92);
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_get_or_try_init_cancellable() {
+    use once_cell::WaitError;
+    use std::{sync::atomic::AtomicBool, time::Duration};
+
+    let cell: sync::OnceCell<i32> = sync::OnceCell::new();
+    let cancel = AtomicBool::new(false);
+    let (started, finish) = (Barrier::new(2), Barrier::new(2));
+    scope(|scope| {
+        let initializer = scope.spawn(|_| {
+            *cell.get_or_init(|| {
+                started.wait();
+                finish.wait();
+                92
+            })
+        });
+        started.wait();
+        let waiter =
+            scope.spawn(|_| cell.get_or_try_init_cancellable(&cancel, || Ok::<_, ()>(62)).err());
+        thread::sleep(Duration::from_millis(50));
+        cancel.store(true, SeqCst);
+        assert_eq!(waiter.join().unwrap(), Some(WaitError::Cancelled));
+        finish.wait();
+        assert_eq!(initializer.join().unwrap(), 92);
+    })
+    .unwrap();
+    assert_eq!(cell.get_or_try_init_cancellable(&cancel, || Ok::<_, ()>(62)), Ok(&92));
+
+    // If the initializer gives up, the waiter runs its own one.
+    let cell: sync::OnceCell<i32> = sync::OnceCell::new();
+    let cancel = AtomicBool::new(false);
+    scope(|scope| {
+        scope.spawn(|_| {
+            cell.get_or_try_init(|| {
+                started.wait();
+                thread::sleep(Duration::from_millis(50));
+                Err(())
+            })
+        });
+        started.wait();
+        assert_eq!(cell.get_or_try_init_cancellable(&cancel, || Ok::<_, ()>(62)), Ok(&62));
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn sync_poison_policy_retry() {
+    let cell: sync::OnceCell<i32> = sync::OnceCell::new();
+    let res = std::panic::catch_unwind(|| cell.get_or_init(|| panic!("kaboom")));
+    assert!(res.is_err());
+    assert!(!cell.is_poisoned());
+    assert_eq!(cell.get_or_init(|| 92), &92);
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn sync_poison_policy_poison() {
+    let cell: sync::OnceCell<i32> =
+        sync::OnceCell::with_poison_policy(policy::PoisonPolicy::Poison);
+    let res = std::panic::catch_unwind(|| cell.get_or_init(|| panic!("kaboom")));
+    assert_eq!(panic_message(res.unwrap_err()), "kaboom");
+    assert!(cell.is_poisoned());
+    assert_eq!(cell.get(), None);
+
+    // Every attempt to touch the value panics, including from other threads,
+    // and the initializer is not called.
+    let res = std::panic::catch_unwind(|| cell.get_or_init(|| unreachable!()));
+    assert!(panic_message(res.unwrap_err()).contains("poisoned"));
+    let res = std::panic::catch_unwind(|| cell.get_or_try_init(|| -> Result<_, ()> { Ok(92) }));
+    assert!(panic_message(res.unwrap_err()).contains("poisoned"));
+    let res = std::panic::catch_unwind(|| cell.set(92));
+    assert!(panic_message(res.unwrap_err()).contains("poisoned"));
+    let res = scope(|scope| scope.spawn(|_| *cell.wait()).join()).unwrap();
+    assert!(res.is_err());
+
+    // Errors don't poison.
+    cell.clear_poison();
+    assert!(!cell.is_poisoned());
+    assert_eq!(cell.get_or_try_init(|| Err(())), Err(()));
+    assert!(!cell.is_poisoned());
+    assert_eq!(cell.get_or_init(|| 92), &92);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_poison_policy_store_payload() {
+    let cell: sync::OnceCell<i32> =
+        sync::OnceCell::with_poison_policy(policy::PoisonPolicy::StorePayload);
+    let res =
+        scope(|scope| scope.spawn(|_| *cell.get_or_init(|| panic!("kaboom"))).join()).unwrap();
+    // The caller gets the same message, the cell keeps the original payload.
+    assert_eq!(panic_message(res.unwrap_err()), "kaboom");
+    assert!(cell.is_poisoned());
+
+    let payload = cell.take_panic_payload().unwrap();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"kaboom"));
+    assert!(cell.take_panic_payload().is_none());
+    assert!(cell.is_poisoned());
+
+    let res = std::panic::catch_unwind(|| cell.get_or_init(|| panic!("kaboom")));
+    assert!(panic_message(res.unwrap_err()).contains("poisoned"));
+    assert!(cell.take_panic_payload().is_none());
+
+    cell.clear_poison();
+    let res = std::panic::catch_unwind(|| {
+        cell.get_or_init(|| std::panic::panic_any("kaboom 92".to_string()))
+    });
+    assert_eq!(panic_message(res.unwrap_err()), "kaboom 92");
+    let payload = cell.take_panic_payload().unwrap();
+    assert_eq!(payload.downcast_ref::<String>().map(String::as_str), Some("kaboom 92"));
+
+    cell.clear_poison();
+    assert_eq!(cell.get_or_init(|| 92), &92);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_poison_payloads_are_dropped_with_cells() {
+    static DROP_CNT: AtomicUsize = AtomicUsize::new(0);
+    struct Payload(usize);
+    impl Drop for Payload {
+        fn drop(&mut self) {
+            DROP_CNT.fetch_add(1, SeqCst);
+        }
+    }
+
+    let poisoned = |n: usize| {
+        let cell: sync::OnceCell<i32> =
+            sync::OnceCell::with_poison_policy(policy::PoisonPolicy::StorePayload);
+        let res = std::panic::catch_unwind(|| {
+            cell.get_or_init(|| std::panic::panic_any(Payload(n)));
+        });
+        // The payload can't be copied, it stays with the cell.
+        assert!(panic_message(res.unwrap_err()).contains("take_panic_payload"));
+        cell
+    };
+    let (c1, c2) = (poisoned(1), poisoned(2));
+    assert_eq!(DROP_CNT.load(SeqCst), 0);
+    assert_eq!(c2.take_panic_payload().unwrap().downcast_ref::<Payload>().unwrap().0, 2);
+    assert_eq!(DROP_CNT.load(SeqCst), 1);
+    drop(c1);
+    assert_eq!(DROP_CNT.load(SeqCst), 2);
+    let c3 = poisoned(3);
+    c3.clear_poison();
+    assert_eq!(DROP_CNT.load(SeqCst), 3);
+    assert!(!c3.is_poisoned());
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn sync_poison_is_shared_by_waiters() {
+    let cell: sync::OnceCell<i32> =
+        sync::OnceCell::with_poison_policy(policy::PoisonPolicy::Poison);
+    let barrier = Barrier::new(2);
+    let n_calls = AtomicUsize::new(0);
+    scope(|scope| {
+        let panicking = scope.spawn(|_| {
+            cell.get_or_init(|| {
+                n_calls.fetch_add(1, SeqCst);
+                barrier.wait();
+                thread::sleep(std::time::Duration::from_millis(50));
+                panic!("kaboom")
+            });
+        });
+        barrier.wait();
+        // Blocks on the running initializer, then observes the poison.
+        let waiting = scope.spawn(|_| {
+            cell.get_or_init(|| {
+                n_calls.fetch_add(1, SeqCst);
+                92
+            });
+        });
+        assert!(panicking.join().is_err());
+        assert!(waiting.join().is_err());
+    })
+    .unwrap();
+    assert_eq!(n_calls.load(SeqCst), 1);
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn sync_lazy_is_poisoned() {
+    let lazy: sync::Lazy<i32> = sync::Lazy::new(|| panic!("kaboom"));
+    assert!(!sync::Lazy::is_poisoned(&lazy));
+    let res = std::panic::catch_unwind(|| *lazy);
+    assert_eq!(panic_message(res.unwrap_err()), "kaboom");
+    assert!(sync::Lazy::is_poisoned(&lazy));
+    let res = std::panic::catch_unwind(|| *lazy);
+    assert!(panic_message(res.unwrap_err()).contains("Lazy instance has previously been poisoned"));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn unsync_state() {
+    use once_cell::CellState;
+
     let cell = unsync::OnceCell::new();
-    cell.set("hello".to_string()).unwrap();
-    assert_eq!(cell.into_inner(), Some("hello".to_string()));
+    assert_eq!(cell.state(), CellState::Uninit);
+    assert_eq!(format!("{:?}", cell), "OnceCell(<uninit>)");
+    cell.get_or_init(|| {
+        let thread = thread::current().id();
+        assert_eq!(cell.state(), CellState::Initializing { thread });
+        assert_eq!(format!("{:?}", cell), "OnceCell(<initializing>)");
+        92
+    });
+    assert_eq!(cell.state(), CellState::Ready);
+    assert_eq!(format!("{:?}", cell), "OnceCell(92)");
 }
 
 #[test]
-fn sync_into_inner() {
-    let cell: sync::OnceCell<String> = sync::OnceCell::new();
-    assert_eq!(cell.into_inner(), None);
-    let cell = sync::OnceCell::new();
-    cell.set("hello".to_string()).unwrap();
+#[cfg(feature = "std")]
+fn sync_state() {
+    use once_cell::CellState;
+
+    let cell = sync::OnceCell::with_poison_policy(policy::PoisonPolicy::Poison);
+    assert_eq!(cell.state(), CellState::Uninit);
+    assert_eq!(format!("{:?}", cell), "OnceCell(<uninit>)");
+
+    let barrier = Barrier::new(2);
+    scope(|scope| {
+        let initializer = scope.spawn(|_| {
+            cell.get_or_init(|| {
+                barrier.wait();
+                barrier.wait();
+                92
+            });
+            thread::current().id()
+        });
+        barrier.wait();
+        // Observed from another thread while the initializer runs.
+        let thread = match cell.state() {
+            CellState::Initializing { thread } => thread,
+            state => panic!("unexpected state: {:?}", state),
+        };
+        assert!(format!("{:?}", cell).starts_with("OnceCell(<initializing by ThreadId("));
+        barrier.wait();
+        assert_eq!(initializer.join().unwrap(), thread);
+    })
+    .unwrap();
+    assert_eq!(cell.state(), CellState::Ready);
+    assert_eq!(format!("{:?}", cell), "OnceCell(92)");
+
+    let cell: sync::OnceCell<i32> =
+        sync::OnceCell::with_poison_policy(policy::PoisonPolicy::Poison);
+    let res = std::panic::catch_unwind(|| cell.get_or_init(|| panic!("kaboom")));
+    assert!(res.is_err());
+    assert_eq!(cell.state(), CellState::Poisoned);
+    assert_eq!(format!("{:?}", cell), "OnceCell(<poisoned>)");
+
+    let lazy: sync::Lazy<i32, _> = sync::Lazy::new(|| 92);
+    assert_eq!(format!("{:?}", lazy), r#"Lazy { cell: OnceCell(<uninit>), init: ".." }"#);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_state_nested() {
+    use once_cell::CellState;
+
+    // Each thread initializes a cell from the initializer of another one,
+    // which is not re-entrant, and the threads reuse the ids of the exited.
+    for _ in 0..4 {
+        let outer = sync::OnceCell::new();
+        let inner = sync::OnceCell::new();
+        thread::spawn(move || {
+            let thread = thread::current().id();
+            outer.get_or_init(|| {
+                *inner.get_or_init(|| {
+                    assert_eq!(outer.state(), CellState::Initializing { thread });
+                    assert_eq!(inner.state(), CellState::Initializing { thread });
+                    92
+                })
+            });
+            assert_eq!(outer.state(), CellState::Ready);
+        })
+        .join()
+        .unwrap();
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_once_map() {
+    let mut map: sync::OnceMap<String, String> = sync::OnceMap::new();
+    assert_eq!(map.get("hello"), None);
+    let hello = map.get_or_init("hello".to_string(), || "world".to_string());
+    assert_eq!(map.get_or_try_init("spam".to_string(), || Err(())), Err(()));
+    assert_eq!(map.get("spam"), None);
+    // Growing the map doesn't invalidate references.
+    for i in 0..100 {
+        map.get_or_init(i.to_string(), || i.to_string());
+    }
+    assert_eq!(hello, "world");
+    assert!(std::ptr::eq(hello, map.get("hello").unwrap()));
+
+    map.get_mut("hello").unwrap().push('!');
+    assert_eq!(map.remove("hello"), Some("world!".to_string()));
+    assert_eq!(map.remove("hello"), None);
+    assert_eq!(map.remove("spam"), None);
+    assert_eq!(map.get("42").map(String::as_str), Some("42"));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_once_map_contended() {
+    let map: sync::OnceMap<usize, usize> = sync::OnceMap::new();
+    let n_calls = AtomicUsize::new(0);
+    scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|_| {
+                for key in 0..16 {
+                    let value = map.get_or_init(key, || {
+                        n_calls.fetch_add(1, SeqCst);
+                        key * 2
+                    });
+                    assert_eq!(*value, key * 2);
+                }
+            });
+        }
+    })
+    .unwrap();
+    assert_eq!(n_calls.load(SeqCst), 16);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_once_map_does_not_lock_during_init() {
+    let map: sync::OnceMap<u32, u32> = sync::OnceMap::new();
+    let barrier = Barrier::new(2);
+    scope(|scope| {
+        // Both initializers must be running at the same time to pass the
+        // barrier, which deadlocks if the map is locked during `f`.
+        for key in 0..2 {
+            let (map, barrier) = (&map, &barrier);
+            scope.spawn(move |_| {
+                map.get_or_init(key, || {
+                    barrier.wait();
+                    key
+                })
+            });
+        }
+    })
+    .unwrap();
+    // Initializers may also use the map for other keys.
+    let value = map.get_or_init(2, || map.get_or_init(3, || 3) + 1);
+    assert_eq!(*value, 4);
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn unsync_once_vec() {
+    let vec = unsync::OnceVec::new();
+    assert!(vec.is_empty());
+    assert_eq!(vec.get(0), None);
+    let (idx, first) = vec.push(0.to_string());
+    assert_eq!(idx, 0);
+    // Growing the vector over several segments doesn't move the elements.
+    for i in 1..100 {
+        assert_eq!(vec.push(i.to_string()).0, i);
+    }
+    assert_eq!(first, "0");
+    assert!(std::ptr::eq(first, vec.get(0).unwrap()));
+    assert_eq!(vec.len(), 100);
+    assert_eq!(vec.get(99).map(String::as_str), Some("99"));
+    assert_eq!(vec.get(100), None);
+    assert_eq!(vec.get(usize::MAX), None);
+    assert_eq!(format!("{:?}", unsync::OnceVec::<u32>::new()), "[]");
+}
+
+#[test]
+#[cfg(all(feature = "alloc", feature = "std"))]
+fn sync_once_vec_contended() {
+    static DROP_CNT: AtomicUsize = AtomicUsize::new(0);
+    struct Dropper(usize);
+    impl Drop for Dropper {
+        fn drop(&mut self) {
+            DROP_CNT.fetch_add(1, SeqCst);
+        }
+    }
+
+    let n_threads = 8;
+    let n_pushes = 1000;
+    let vec = sync::OnceVec::new();
+    scope(|scope| {
+        for _ in 0..n_threads {
+            let vec = &vec;
+            scope.spawn(move |_| {
+                for i in 0..n_pushes {
+                    let (idx, value) = vec.push(Dropper(i));
+                    assert_eq!(value.0, i);
+                    assert!(std::ptr::eq(value, vec.get(idx).unwrap()));
+                }
+            });
+        }
+    })
+    .unwrap();
+    assert_eq!(vec.len(), n_threads * n_pushes);
+    let sum: usize = (0..vec.len()).map(|idx| vec.get(idx).unwrap().0).sum();
+    assert_eq!(sum, n_threads * n_pushes * (n_pushes - 1) / 2);
+    assert!(vec.get(vec.len()).is_none());
+    drop(vec);
+    assert_eq!(DROP_CNT.load(SeqCst), n_threads * n_pushes);
+}
+
+#[test]
+fn unsync_lazy_table() {
+    let n_calls = Cell::new(0);
+    let table: unsync::LazyTable<usize, 4, _> = unsync::LazyTable::new(|i| {
+        n_calls.set(n_calls.get() + 1);
+        i * 10
+    });
+    assert_eq!(table.len(), 4);
+    assert_eq!(table.get_if_init(2), None);
+    assert_eq!(*table.get(2), 20);
+    assert_eq!(*table.get(2), 20);
+    assert_eq!(n_calls.get(), 1);
+    assert_eq!(table.get_if_init(2), Some(&20));
+    assert_eq!(table.get_if_init(1), None);
+    assert_eq!(table.get_if_init(4), None);
+    assert_eq!(
+        format!("{:?}", table),
+        "[OnceCell(<uninit>), OnceCell(<uninit>), OnceCell(20), OnceCell(<uninit>)]"
+    );
+}
+
+#[test]
+#[should_panic]
+fn unsync_lazy_table_out_of_bounds() {
+    let table: unsync::LazyTable<usize, 4> = unsync::LazyTable::new(|i| i);
+    table.get(4);
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn sync_lazy_table() {
+    static N_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static TABLE: sync::LazyTable<String, 16> = sync::LazyTable::new(|i| {
+        N_CALLS.fetch_add(1, SeqCst);
+        i.to_string()
+    });
+
+    scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|_| {
+                for i in (0..16).step_by(2) {
+                    assert_eq!(TABLE.get(i), &i.to_string());
+                }
+            });
+        }
+    })
+    .unwrap();
+    assert_eq!(N_CALLS.load(SeqCst), 8);
+    assert_eq!(TABLE.get_if_init(1), None);
+}
+
+/// Wraps `parking_lot::RawMutex`, counting how many times it is locked in
+/// `N_LOCKS[ID]`. Each test uses its own `ID`, as tests run concurrently.
+#[cfg(all(once_cell_sync, feature = "lock_api"))]
+struct CountingMutex<const ID: usize>(parking_lot::RawMutex);
+
+#[cfg(all(once_cell_sync, feature = "lock_api"))]
+static N_LOCKS: [AtomicUsize; 2] = [AtomicUsize::new(0), AtomicUsize::new(0)];
+
+#[cfg(all(once_cell_sync, feature = "lock_api"))]
+unsafe impl<const ID: usize> lock_api::RawMutex for CountingMutex<ID> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = CountingMutex(<parking_lot::RawMutex as lock_api::RawMutex>::INIT);
+    type GuardMarker = lock_api::GuardSend;
+
+    fn lock(&self) {
+        N_LOCKS[ID].fetch_add(1, SeqCst);
+        lock_api::RawMutex::lock(&self.0)
+    }
+
+    fn try_lock(&self) -> bool {
+        let locked = lock_api::RawMutex::try_lock(&self.0);
+        if locked {
+            N_LOCKS[ID].fetch_add(1, SeqCst);
+        }
+        locked
+    }
+
+    fn unlock(&self) {
+        lock_api::RawMutex::unlock(&self.0)
+    }
+}
+
+#[test]
+#[cfg(all(once_cell_sync, feature = "lock_api"))]
+fn sync_once_cell_with() {
+    static CELL: sync::OnceCellWith<usize, CountingMutex<0>> = sync::OnceCellWith::new();
+    static N_CALLS: AtomicUsize = AtomicUsize::new(0);
+    let barrier = Barrier::new(8);
+
+    assert_eq!(CELL.get(), None);
+    scope(|scope| {
+        for i in 0..8 {
+            let barrier = &barrier;
+            scope.spawn(move |_| {
+                barrier.wait();
+                let value = CELL.get_or_init(|| {
+                    N_CALLS.fetch_add(1, SeqCst);
+                    i
+                });
+                assert!(*value < 8);
+            });
+        }
+    })
+    .unwrap();
+    assert_eq!(N_CALLS.load(SeqCst), 1);
+    // Threads which raced with the initializer locked the mutex, but reads
+    // of the initialized cell don't.
+    let n_locks = N_LOCKS[0].load(SeqCst);
+    assert!((1..=8).contains(&n_locks));
+    assert!(CELL.set(92).is_err());
+    assert_eq!(N_LOCKS[0].load(SeqCst), n_locks);
+}
+
+#[test]
+#[cfg(all(once_cell_sync, feature = "lock_api", feature = "std"))]
+fn sync_once_cell_with_timeouts() {
+    use once_cell::WaitError;
+    use std::{sync::atomic::AtomicBool, time::Duration};
+
+    let cell: sync::OnceCellWith<i32, CountingMutex<1>> = sync::OnceCellWith::new();
+    // The initializers of the timed and cancellable variants hold the mutex.
+    let res = cell.get_or_try_init_timeout(Duration::from_secs(0), || Err("nope"));
+    assert_eq!(res, Err(WaitError::Init("nope")));
+    let cancel = AtomicBool::new(true);
+    let res = cell.get_or_try_init_cancellable(&cancel, || Err("nope"));
+    assert_eq!(res, Err(WaitError::Init("nope")));
+    assert_eq!(N_LOCKS[1].load(SeqCst), 2);
+
+    let (started, finish) = (Barrier::new(2), Barrier::new(2));
+    scope(|scope| {
+        let initializer = scope.spawn(|_| {
+            *cell.get_or_init(|| {
+                started.wait();
+                finish.wait();
+                92
+            })
+        });
+        started.wait();
+        // Threads which give up waiting for the mutex don't lock it.
+        let res = cell.get_or_try_init_timeout(Duration::from_millis(50), || Ok::<_, ()>(62));
+        assert_eq!(res, Err(WaitError::Timeout));
+        let res = cell.get_or_try_init_cancellable(&cancel, || Ok::<_, ()>(62));
+        assert_eq!(res, Err(WaitError::Cancelled));
+        assert_eq!(N_LOCKS[1].load(SeqCst), 3);
+        finish.wait();
+        assert_eq!(initializer.join().unwrap(), 92);
+    })
+    .unwrap();
+    let res = cell.get_or_try_init_timeout(Duration::from_secs(0), || Ok::<_, ()>(62));
+    assert_eq!(res, Ok(&92));
+    assert_eq!(N_LOCKS[1].load(SeqCst), 3);
+}
+
+#[test]
+#[cfg(all(once_cell_sync, feature = "lock_api"))]
+fn sync_once_cell_with_failure_and_take() {
+    let mut cell: sync::OnceCellWith<String, parking_lot::RawMutex> = sync::OnceCellWith::new();
+    assert_eq!(cell.get_or_try_init(|| Err(())), Err(()));
+    assert_eq!(cell.get(), None);
+    assert_eq!(cell.get_or_try_init(|| Ok::<_, ()>("hello".to_string())).unwrap(), "hello");
+    assert_eq!(format!("{:?}", cell), "OnceCellWith(\"hello\")");
+    assert_eq!(cell.take().as_deref(), Some("hello"));
+    assert_eq!(format!("{:?}", cell), "OnceCellWith(<uninit>)");
+    assert!(cell.set("world".to_string()).is_ok());
+    assert_eq!(cell.into_inner().as_deref(), Some("world"));
+}
+
+#[test]
+#[cfg(all(feature = "lock_api", feature = "std"))]
+#[should_panic(expected = "reentrant init of `sync::OnceCellWith<i32, parking_lot::")]
+fn sync_once_cell_with_reentrant_init() {
+    let cell: sync::OnceCellWith<i32, parking_lot::RawMutex> = sync::OnceCellWith::new();
+    cell.get_or_init(|| *cell.get_or_init(|| 92));
+}
+
+#[test]
+#[cfg(all(once_cell_sync, feature = "lock_api"))]
+fn sync_once_cell_with_poison_and_wait() {
+    let cell: sync::OnceCellWith<i32, parking_lot::RawMutex> =
+        sync::OnceCellWith::with_poison_policy(policy::PoisonPolicy::Poison);
+    let res = std::panic::catch_unwind(|| cell.get_or_init(|| panic!("kaboom")));
+    assert!(res.is_err());
+    assert!(cell.is_poisoned());
+    assert_eq!(format!("{:?}", cell), "OnceCellWith(<poisoned>)");
+    // The mutex was unlocked while unwinding.
+    cell.clear_poison();
+    scope(|scope| {
+        scope.spawn(|_| cell.set(92).unwrap());
+        assert_eq!(cell.wait(), &92);
+    })
+    .unwrap();
+
+    let mut lazy: sync::LazyWith<i32, parking_lot::RawMutex> = sync::LazyWith::new(|| 92);
+    assert_eq!(sync::LazyWith::get_mut(&mut lazy), None);
+    assert_eq!(*lazy, 92);
+    *sync::LazyWith::get_mut(&mut lazy).unwrap() += 1;
+    assert_eq!(*lazy, 93);
+    assert!(!sync::LazyWith::is_poisoned(&lazy));
+}
+
+#[test]
+#[cfg(all(once_cell_sync, feature = "lock_api"))]
+fn sync_lazy_with() {
+    static N_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static LAZY: sync::LazyWith<String, parking_lot::RawMutex> = sync::LazyWith::new(|| {
+        N_CALLS.fetch_add(1, SeqCst);
+        "hello".to_string()
+    });
+
+    assert_eq!(sync::LazyWith::get(&LAZY), None);
+    scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|_| assert_eq!(&*LAZY, "hello"));
+        }
+    })
+    .unwrap();
+    assert_eq!(N_CALLS.load(SeqCst), 1);
+    assert_eq!(sync::LazyWith::get(&LAZY).map(String::as_str), Some("hello"));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_reloadable() {
+    static DROP_CNT: AtomicUsize = AtomicUsize::new(0);
+    #[derive(Debug)]
+    struct Dropper(u32);
+    impl Drop for Dropper {
+        fn drop(&mut self) {
+            DROP_CNT.fetch_add(1, SeqCst);
+        }
+    }
+
+    let cell = sync::Reloadable::new(Dropper(1));
+    let first = cell.get();
+    let old = cell.reload(|| Dropper(cell.get().0 + 1));
+    assert_eq!(old.0, 1);
+    assert_eq!(cell.get().0, 2);
+    assert_eq!(format!("{:?}", cell), "Reloadable(Dropper(2))");
+    assert!(cell.try_reload(|| Err(())).is_err());
+    assert_eq!(cell.get().0, 2);
+
+    // The old value lives as long as its guards.
+    drop(old);
+    assert_eq!(first.0, 1);
+    assert_eq!(DROP_CNT.load(SeqCst), 0);
+    drop(first);
+    assert_eq!(DROP_CNT.load(SeqCst), 1);
+    drop(cell);
+    assert_eq!(DROP_CNT.load(SeqCst), 2);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_reloadable_contended() {
+    let cell = sync::Reloadable::new(vec![0; 16]);
+    let n_reloads = 100;
+    scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|_| loop {
+                let value = cell.get();
+                // Readers never see a torn value.
+                assert!(value.iter().all(|&it| it == value[0]));
+                if value[0] == n_reloads {
+                    break;
+                }
+            });
+        }
+        scope.spawn(|_| {
+            for i in 1..=n_reloads {
+                cell.reload(|| vec![i; 16]);
+            }
+        });
+    })
+    .unwrap();
+    assert_eq!(cell.get()[0], n_reloads);
+}
+
+#[cfg(feature = "std")]
+struct ManualClock {
+    now: std::sync::Mutex<std::time::Instant>,
+}
+
+#[cfg(feature = "std")]
+impl ManualClock {
+    fn new() -> ManualClock {
+        ManualClock { now: std::sync::Mutex::new(std::time::Instant::now()) }
+    }
+
+    fn advance(&self, by: std::time::Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+#[cfg(feature = "std")]
+impl sync::Clock for &ManualClock {
+    fn now(&self) -> std::time::Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_expiring_lazy() {
+    let clock = ManualClock::new();
+    let n_calls = AtomicUsize::new(0);
+    let lazy = sync::ExpiringLazy::with_clock(
+        std::time::Duration::from_secs(10),
+        || n_calls.fetch_add(1, SeqCst),
+        &clock,
+    );
+    assert_eq!(*lazy.get(), 0);
+    clock.advance(std::time::Duration::from_secs(9));
+    assert_eq!(*lazy.get(), 0);
+    clock.advance(std::time::Duration::from_secs(1));
+    let old = lazy.get();
+    assert_eq!(*old, 1);
+    assert_eq!(*lazy.get(), 1);
+    clock.advance(std::time::Duration::from_secs(25));
+    assert_eq!(*lazy.get(), 2);
+    assert_eq!(*old, 1);
+    assert_eq!(n_calls.load(SeqCst), 3);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_expiring_lazy_serves_stale_value_during_refresh() {
+    let clock = ManualClock::new();
+    let n_calls = AtomicUsize::new(0);
+    let (started, finish) = (Barrier::new(2), Barrier::new(2));
+    let lazy = sync::ExpiringLazy::with_clock(
+        std::time::Duration::from_secs(10),
+        || {
+            let n = n_calls.fetch_add(1, SeqCst);
+            if n == 1 {
+                started.wait();
+                finish.wait();
+            }
+            n
+        },
+        &clock,
+    );
+    assert_eq!(*lazy.get(), 0);
+    clock.advance(std::time::Duration::from_secs(10));
+    scope(|scope| {
+        scope.spawn(|_| assert_eq!(*lazy.get(), 1));
+        started.wait();
+        // The refresh is in progress, other threads see the stale value
+        // without running the initializer.
+        assert_eq!(*lazy.get(), 0);
+        finish.wait();
+    })
+    .unwrap();
+    assert_eq!(*lazy.get(), 1);
+    assert_eq!(n_calls.load(SeqCst), 2);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn sync_expiring_lazy_panic_keeps_stale_value() {
+    let clock = ManualClock::new();
+    let n_calls = Cell::new(0);
+    let lazy = sync::ExpiringLazy::with_clock(
+        std::time::Duration::from_secs(1),
+        || {
+            n_calls.set(n_calls.get() + 1);
+            if n_calls.get() == 2 {
+                panic!("refresh failed")
+            }
+            n_calls.get()
+        },
+        &clock,
+    );
+    assert_eq!(*lazy.get(), 1);
+    clock.advance(std::time::Duration::from_secs(1));
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| lazy.get()));
+    assert!(res.is_err());
+    assert_eq!(*lazy.get(), 3);
+}
+
+#[test]
+#[cfg(all(once_cell_sync, feature = "serde"))]
+fn serde_round_trip() {
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Config {
+        name: String,
+        unsync_derived: unsync::OnceCell<u32>,
+        sync_derived: sync::OnceCell<Vec<u32>>,
+    }
+
+    let config = Config {
+        name: "spam".to_string(),
+        unsync_derived: 92.into(),
+        sync_derived: sync::OnceCell::new(),
+    };
+    let json = serde_json::to_string(&config).unwrap();
+    assert_eq!(json, r#"{"name":"spam","unsync_derived":92,"sync_derived":null}"#);
+
+    let config: Config = serde_json::from_str(json.as_str()).unwrap();
+    assert_eq!(config.name, "spam");
+    assert_eq!(config.unsync_derived.get(), Some(&92));
+    assert_eq!(config.sync_derived.get(), None);
+
+    let config: Config =
+        serde_json::from_str(r#"{"name":"eggs","unsync_derived":null,"sync_derived":[1,2]}"#)
+            .unwrap();
+    assert_eq!(config.unsync_derived.get(), None);
+    assert_eq!(config.sync_derived.get(), Some(&vec![1, 2]));
+}
+
+#[test]
+#[cfg(all(once_cell_sync, feature = "serde"))]
+fn serde_lazy_is_forced() {
+    let lazy: unsync::Lazy<Vec<u32>> = unsync::Lazy::new(|| vec![1, 2]);
+    assert_eq!(serde_json::to_string(&lazy).unwrap(), "[1,2]");
+    let lazy: sync::Lazy<String> = sync::Lazy::new(|| "hello".to_string());
+    assert_eq!(serde_json::to_string(&lazy).unwrap(), r#""hello""#);
+    assert_eq!(sync::Lazy::get(&lazy).map(String::as_str), Some("hello"));
+}
+
+#[test]
+fn unsync_try_lazy() {
+    let n_calls = Cell::new(0);
+    let lazy: unsync::TryLazy<i32, i32, _> = unsync::TryLazy::new(|| {
+        n_calls.set(n_calls.get() + 1);
+        if n_calls.get() < 3 {
+            Err(n_calls.get())
+        } else {
+            Ok(92)
+        }
+    });
+    assert_eq!(lazy.get(), None);
+    assert_eq!(lazy.force(), Err(1));
+    assert_eq!(lazy.force(), Err(2));
+    assert_eq!(lazy.error(), None);
+    assert_eq!(lazy.force(), Ok(&92));
+    assert_eq!(lazy.force(), Ok(&92));
+    assert_eq!(lazy.get(), Some(&92));
+    assert_eq!(n_calls.get(), 3);
+
+    // Only `CacheError` needs room for the error.
+    type Init = fn() -> Result<u8, [u8; 64]>;
+    assert_eq!(
+        mem::size_of::<unsync::TryLazy<u8, [u8; 64], Init>>(),
+        mem::size_of::<(unsync::OnceCell<u8>, Init)>()
+    );
+    assert!(mem::size_of::<unsync::TryLazy<u8, [u8; 64], Init, policy::CacheError>>() > 64);
+}
+
+#[test]
+fn unsync_try_lazy_cache_error() {
+    let n_calls = Cell::new(0);
+    let lazy: unsync::TryLazy<i32, String, _, policy::CacheError> = unsync::TryLazy::new(|| {
+        n_calls.set(n_calls.get() + 1);
+        Err("kaboom".to_string())
+    });
+    assert_eq!(lazy.force(), Err("kaboom".to_string()));
+    assert_eq!(lazy.force(), Err("kaboom".to_string()));
+    assert_eq!(lazy.error(), Some(&"kaboom".to_string()));
+    assert_eq!(lazy.get(), None);
+    assert_eq!(n_calls.get(), 1);
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn sync_try_lazy() {
+    static N_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static LAZY: sync::TryLazy<usize, usize> = sync::TryLazy::new(|| {
+        let n = N_CALLS.fetch_add(1, SeqCst);
+        if n == 0 {
+            Err(n)
+        } else {
+            Ok(92)
+        }
+    });
+
+    assert_eq!(LAZY.force(), Err(0));
+    scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|_| assert_eq!(LAZY.force(), Ok(&92)));
+        }
+    })
+    .unwrap();
+    assert_eq!(N_CALLS.load(SeqCst), 2);
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn sync_try_lazy_cache_error() {
+    let n_calls = AtomicUsize::new(0);
+    let lazy: sync::TryLazy<i32, String, _, policy::CacheError> = sync::TryLazy::new(|| {
+        n_calls.fetch_add(1, SeqCst);
+        Err("kaboom".to_string())
+    });
+    scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|_| assert_eq!(lazy.force(), Err("kaboom".to_string())));
+        }
+    })
+    .unwrap();
+    assert_eq!(lazy.error(), Some(&"kaboom".to_string()));
+    assert_eq!(n_calls.load(SeqCst), 1);
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn from_impl() {
+    assert_eq!(sync::OnceCell::from("value").get(), Some(&"value"));
+    assert_eq!(unsync::OnceCell::from("value").get(), Some(&"value"));
+    assert_ne!(sync::OnceCell::from("foo").get(), Some(&"bar"));
+    assert_ne!(unsync::OnceCell::from("foo").get(), Some(&"bar"));
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn partialeq_impl() {
+    assert!(sync::OnceCell::from("value") == sync::OnceCell::from("value"));
+    assert!(sync::OnceCell::from("foo") != sync::OnceCell::from("bar"));
+    assert!(unsync::OnceCell::from("value") == unsync::OnceCell::from("value"));
+    assert!(unsync::OnceCell::from("foo") != unsync::OnceCell::from("bar"));
+
+    assert!(sync::OnceCell::<String>::new() == sync::OnceCell::new());
+    assert!(sync::OnceCell::<String>::new() != sync::OnceCell::from("value".to_owned()));
+    assert!(unsync::OnceCell::<String>::new() == unsync::OnceCell::new());
+    assert!(unsync::OnceCell::<String>::new() != unsync::OnceCell::from("value".to_owned()));
+}
+
+#[test]
+// The cells are never initialized while in the set.
+#[allow(clippy::mutable_key_type)]
+#[cfg(once_cell_sync)]
+fn ord_hash_impls() {
+    use std::collections::HashSet;
+
+    let mut cells = vec![sync::OnceCell::from(2), sync::OnceCell::new(), sync::OnceCell::from(1)];
+    cells.sort();
+    assert_eq!(
+        cells,
+        vec![sync::OnceCell::new(), sync::OnceCell::from(1), sync::OnceCell::from(2)]
+    );
+    let mut cells =
+        vec![unsync::OnceCell::from(2), unsync::OnceCell::new(), unsync::OnceCell::from(1)];
+    cells.sort();
+    assert_eq!(
+        cells,
+        vec![unsync::OnceCell::new(), unsync::OnceCell::from(1), unsync::OnceCell::from(2)]
+    );
+
+    let set: HashSet<sync::OnceCell<u32>> =
+        vec![sync::OnceCell::from(1), sync::OnceCell::new(), sync::OnceCell::from(1)]
+            .into_iter()
+            .collect();
+    assert_eq!(set.len(), 2);
+    assert!(set.contains(&sync::OnceCell::new()));
+    let set: HashSet<unsync::OnceCell<u32>> =
+        vec![unsync::OnceCell::from(1), unsync::OnceCell::new(), unsync::OnceCell::from(1)]
+            .into_iter()
+            .collect();
+    assert_eq!(set.len(), 2);
+    assert!(set.contains(&unsync::OnceCell::from(1)));
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn display_impl() {
+    assert_eq!(sync::OnceCell::from(92).to_string(), "92");
+    assert_eq!(sync::OnceCell::<u32>::new().to_string(), "<uninit>");
+    assert_eq!(format!("{:>4}", unsync::OnceCell::from(92)), "  92");
+    assert_eq!(unsync::OnceCell::<u32>::new().to_string(), "<uninit>");
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn option_conversions() {
+    let cell: sync::OnceCell<u32> = Some(92).into();
+    assert_eq!(cell.get(), Some(&92));
+    assert_eq!(Option::from(cell), Some(92));
+    let cell: sync::OnceCell<u32> = None.into();
+    assert_eq!(Option::<u32>::from(cell), None);
+
+    let cell: unsync::OnceCell<u32> = Some(92).into();
+    assert_eq!(cell.get(), Some(&92));
+    assert_eq!(Option::from(cell), Some(92));
+    let cell: unsync::OnceCell<u32> = None.into();
+    assert_eq!(Option::<u32>::from(cell), None);
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn lazy_default() {
+    let lazy: sync::Lazy<Vec<u32>> = Default::default();
+    assert!(lazy.is_empty());
+    let lazy: unsync::Lazy<String> = Default::default();
+    assert_eq!(&*lazy, "");
+}
+
+#[test]
+fn unsync_get_mut_take() {
+    let mut cell: unsync::OnceCell<String> = unsync::OnceCell::new();
+    assert_eq!(cell.get_mut(), None);
+    assert_eq!(cell.take(), None);
+
+    cell.get_or_init_mut(|| "hello".to_string()).push_str(", world");
+    assert_eq!(cell.get().map(String::as_str), Some("hello, world"));
+    cell.get_mut().unwrap().make_ascii_uppercase();
+    assert_eq!(cell.take(), Some("HELLO, WORLD".to_string()));
+
+    assert_eq!(cell.get(), None);
+    assert_eq!(cell.get_or_init(|| "again".to_string()), "again");
+}
+
+#[test]
+#[cfg(once_cell_sync)]
+fn sync_get_mut_take() {
+    let mut cell: sync::OnceCell<String> = sync::OnceCell::new();
+    assert_eq!(cell.get_mut(), None);
+    assert_eq!(cell.take(), None);
+
+    cell.get_or_init_mut(|| "hello".to_string()).push_str(", world");
+    assert_eq!(cell.get().map(String::as_str), Some("hello, world"));
+    cell.get_mut().unwrap().make_ascii_uppercase();
+    assert_eq!(cell.take(), Some("HELLO, WORLD".to_string()));
+
+    // The cell is really reset and can be initialized again, by another
+    // thread as well.
+    assert_eq!(cell.get(), None);
+    go(|| {
+        assert_eq!(cell.get_or_try_init(|| Err(())), Err(()));
+        assert_eq!(cell.set("again".to_string()), Ok(()));
+    });
+    assert_eq!(cell.get().map(String::as_str), Some("again"));
+}
+
+#[test]
+fn unsync_into_inner() {
+    let cell: unsync::OnceCell<String> = unsync::OnceCell::new();
+    assert_eq!(cell.into_inner(), None);
+    let cell = unsync::OnceCell::new();
+    cell.set("hello".to_string()).unwrap();
     assert_eq!(cell.into_inner(), Some("hello".to_string()));
 }
+
+#[test]
+#[cfg(once_cell_sync)]
+fn sync_into_inner() {
+    let cell: sync::OnceCell<String> = sync::OnceCell::new();
+    assert_eq!(cell.into_inner(), None);
+    let cell = sync::OnceCell::new();
+    cell.set("hello".to_string()).unwrap();
+    assert_eq!(cell.into_inner(), Some("hello".to_string()));
+}
+
+#[test]
+fn race_once_non_zero_usize() {
+    use std::num::NonZeroUsize;
+
+    let cell = race::OnceNonZeroUsize::new();
+    assert!(cell.get().is_none());
+    let n_calls = AtomicUsize::new(0);
+    scope(|scope| {
+        for i in 1..=8 {
+            let cell = &cell;
+            let n_calls = &n_calls;
+            scope.spawn(move |_| {
+                let value = cell.get_or_init(|| {
+                    n_calls.fetch_add(1, SeqCst);
+                    NonZeroUsize::new(i).unwrap()
+                });
+                assert_eq!(Some(value), cell.get());
+            });
+        }
+    })
+    .unwrap();
+    assert!(n_calls.load(SeqCst) >= 1);
+    let value = cell.get().unwrap();
+    assert_eq!(cell.set(NonZeroUsize::new(92).unwrap()), Err(()));
+    assert_eq!(cell.get(), Some(value));
+}
+
+#[test]
+fn race_once_bool() {
+    let cell = race::OnceBool::new();
+    assert!(cell.get().is_none());
+    assert_eq!(cell.get_or_try_init(|| Err(())), Err(()));
+    assert!(!cell.get_or_init(|| false));
+    assert_eq!(cell.set(true), Err(()));
+    assert_eq!(cell.get(), Some(false));
+
+    let cell = race::OnceBool::new();
+    assert_eq!(cell.set(true), Ok(()));
+    assert!(cell.get_or_init(|| false));
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn race_once_box_drops_losers() {
+    use std::sync::Barrier;
+
+    static DROP_CNT: AtomicUsize = AtomicUsize::new(0);
+    struct Dropper(usize);
+    impl Drop for Dropper {
+        fn drop(&mut self) {
+            DROP_CNT.fetch_add(1, SeqCst);
+        }
+    }
+
+    let n_threads = 8;
+    let cell = race::OnceBox::new();
+    let barrier = Barrier::new(n_threads);
+    let n_calls = AtomicUsize::new(0);
+    scope(|scope| {
+        for i in 0..n_threads {
+            let cell = &cell;
+            let barrier = &barrier;
+            let n_calls = &n_calls;
+            scope.spawn(move |_| {
+                let value = cell.get_or_init(|| {
+                    n_calls.fetch_add(1, SeqCst);
+                    barrier.wait();
+                    Box::new(Dropper(i))
+                });
+                assert!(value.0 < n_threads);
+            });
+        }
+    })
+    .unwrap();
+    // Everyone ran the initializer, all boxes but the winner are dropped.
+    assert_eq!(n_calls.load(SeqCst), n_threads);
+    assert_eq!(DROP_CNT.load(SeqCst), n_threads - 1);
+    assert!(cell.set(Box::new(Dropper(92))).is_err());
+    assert_eq!(DROP_CNT.load(SeqCst), n_threads);
+    drop(cell);
+    assert_eq!(DROP_CNT.load(SeqCst), n_threads + 1);
+}
+
+#[cfg(feature = "async")]
+mod future {
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
+            Arc,
+        },
+        task::{Context, Poll, Wake, Waker},
+        thread::{self, Thread},
+    };
+
+    use crossbeam_utils::thread::scope;
+
+    use once_cell::future::OnceCell;
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Minimal executor: polls `fut` on the current thread, parking it until
+    /// the future is woken up.
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let mut fut = Box::pin(fut);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(res) => return res,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    fn poll_once<F: Future>(fut: Pin<&mut F>) -> Poll<F::Output> {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        fut.poll(&mut Context::from_waker(&waker))
+    }
+
+    /// Returns `Pending` until `flag` is set, asking to be polled again.
+    struct WaitFor<'a>(&'a AtomicBool);
+
+    impl Future for WaitFor<'_> {
+        type Output = ();
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0.load(SeqCst) {
+                return Poll::Ready(());
+            }
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn get_or_init_async() {
+        let cell = OnceCell::new();
+        assert_eq!(cell.get(), None);
+        assert_eq!(block_on(cell.get_or_init_async(|| async { 92 })), &92);
+        assert_eq!(block_on(cell.get_or_init_async(|| async { unreachable!() })), &92);
+        assert_eq!(cell.into_inner(), Some(92));
+    }
+
+    #[test]
+    fn get_or_try_init_async() {
+        let cell: OnceCell<u32> = OnceCell::new();
+        assert_eq!(block_on(cell.get_or_try_init_async(|| async { Err(()) })), Err(()));
+        assert_eq!(cell.get(), None);
+        assert_eq!(block_on(cell.get_or_try_init_async(|| async { Ok::<_, ()>(92) })), Ok(&92));
+    }
+
+    #[test]
+    fn waiters_do_not_block_the_thread() {
+        let cell = OnceCell::new();
+        let ready = AtomicBool::new(false);
+
+        let mut first = Box::pin(cell.get_or_init_async(|| async {
+            WaitFor(&ready).await;
+            1
+        }));
+        let mut second = Box::pin(cell.get_or_init_async(|| async { 2 }));
+        assert!(poll_once(first.as_mut()).is_pending());
+        // On a single thread, the second task is suspended rather than blocked.
+        assert!(poll_once(second.as_mut()).is_pending());
+
+        ready.store(true, SeqCst);
+        assert_eq!(poll_once(first.as_mut()), Poll::Ready(&1));
+        assert_eq!(poll_once(second.as_mut()), Poll::Ready(&1));
+    }
+
+    #[test]
+    fn cancelled_initializer_lets_waiters_retry() {
+        let cell = OnceCell::new();
+        let never = AtomicBool::new(false);
+
+        let mut first = Box::pin(cell.get_or_init_async(|| async {
+            WaitFor(&never).await;
+            1
+        }));
+        let mut second = Box::pin(cell.get_or_init_async(|| async { 2 }));
+        assert!(poll_once(first.as_mut()).is_pending());
+        assert!(poll_once(second.as_mut()).is_pending());
+
+        drop(first);
+        assert_eq!(poll_once(second.as_mut()), Poll::Ready(&2));
+        assert_eq!(cell.get(), Some(&2));
+    }
+
+    #[test]
+    fn get_or_init_async_contended() {
+        static CELL: OnceCell<usize> = OnceCell::new();
+        static N_CALLS: AtomicUsize = AtomicUsize::new(0);
+        let ready = AtomicBool::new(false);
+
+        scope(|scope| {
+            for i in 0..8 {
+                let ready = &ready;
+                scope.spawn(move |_| {
+                    let value = block_on(CELL.get_or_init_async(|| async move {
+                        N_CALLS.fetch_add(1, SeqCst);
+                        WaitFor(ready).await;
+                        i
+                    }));
+                    assert!(*value < 8);
+                });
+            }
+            ready.store(true, SeqCst);
+        })
+        .unwrap();
+        assert_eq!(N_CALLS.load(SeqCst), 1);
+    }
+}